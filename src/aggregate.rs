@@ -0,0 +1,255 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+
+use chrono::DateTime;
+use clap::ValueEnum;
+use futures::future;
+use rss::validation::Validate;
+use rss::{Category, Channel, ChannelBuilder, Item};
+use tokio::sync::RwLock;
+use tracing::warn;
+use warp::{Rejection, Reply};
+
+use crate::bilibili::bangumi::{self, BangumiQuery};
+use crate::bilibili::blacklist::Blacklist;
+use crate::bilibili::live::{self, LiveRooms};
+use crate::bilibili::popular;
+use crate::bilibili::rss_generator::{self, ItemRenderer};
+use crate::cache::{CacheKey, RssCache};
+use crate::ddys;
+use crate::ddys::detail::Details;
+use crate::error::MyError;
+use crate::feed::{self, Conditions};
+use crate::template::Templates;
+
+/// Name of the merged feed cache
+pub const SOURCE: &str = "aggregate";
+
+/// Feeds without parameters that can be merged into GET /feed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum AggregateSource {
+    // GET /bilibili/feed
+    Bilibili,
+    Popular,
+    Weekly,
+    Live,
+    // every season of the anime timeline
+    Bangumi,
+    Ddys,
+}
+
+impl AggregateSource {
+    /// Tag of the merged items, e.g. popular
+    fn name(self) -> &'static str {
+        match self {
+            AggregateSource::Bilibili => "bilibili",
+            AggregateSource::Popular => "popular",
+            AggregateSource::Weekly => "weekly",
+            AggregateSource::Live => "live",
+            AggregateSource::Bangumi => "bangumi",
+            AggregateSource::Ddys => "ddys",
+        }
+    }
+}
+
+/// Everything the merged feeds need, the sources are in the configured order
+pub struct Aggregator {
+    pub sources: Vec<AggregateSource>,
+    pub blacklist: Arc<RwLock<Blacklist>>,
+    pub cache: Arc<RwLock<RssCache>>,
+    pub renderer: Arc<ItemRenderer>,
+    pub templates: Arc<Templates>,
+    pub ddys_details: Option<Arc<Details>>,
+    pub live_rooms: Arc<RwLock<LiveRooms>>,
+}
+
+impl Aggregator {
+    /// Get the feed through its own handler, so it is cached and filtered as usual
+    async fn fetch(&self, source: AggregateSource) -> Result<Channel, Rejection> {
+        let conditions = Conditions::default();
+        let response = match source {
+            AggregateSource::Bilibili => rss_generator::generate_rss(
+                Arc::clone(&self.blacklist),
+                Arc::clone(&self.cache),
+                Arc::clone(&self.renderer),
+                conditions,
+            )
+            .await?
+            .into_response(),
+            AggregateSource::Popular => popular::generate_popular(
+                Arc::clone(&self.blacklist),
+                Arc::clone(&self.cache),
+                Arc::clone(&self.renderer),
+                conditions,
+            )
+            .await?
+            .into_response(),
+            AggregateSource::Weekly => popular::generate_weekly(
+                Arc::clone(&self.blacklist),
+                Arc::clone(&self.cache),
+                Arc::clone(&self.renderer),
+                conditions,
+            )
+            .await?
+            .into_response(),
+            AggregateSource::Live => live::generate_rss(
+                Arc::clone(&self.live_rooms),
+                Arc::clone(&self.cache),
+                conditions,
+            )
+            .await?
+            .into_response(),
+            AggregateSource::Bangumi => {
+                bangumi::generate_rss(Arc::clone(&self.cache), BangumiQuery::default(), conditions)
+                    .await?
+                    .into_response()
+            }
+            AggregateSource::Ddys => ddys::rss_generator::generate_rss(
+                Arc::clone(&self.cache),
+                Arc::clone(&self.templates),
+                self.ddys_details.clone(),
+                conditions,
+            )
+            .await?
+            .into_response(),
+        };
+
+        // without conditions the body is the plain rss
+        let body = warp::hyper::body::to_bytes(response.into_body())
+            .await
+            .map_err(|e| MyError::Aggregate(e.to_string()))?;
+        Channel::read_from(&body[..]).map_err(|e| MyError::Aggregate(e.to_string()).into())
+    }
+}
+
+/// GET /feed, items of every configured source in one channel, newest first
+pub async fn generate_rss(
+    aggregator: Arc<Aggregator>,
+    conditions: Conditions,
+) -> Result<impl Reply, Rejection> {
+    let key = CacheKey::new(SOURCE);
+    if let Some(content) = aggregator.cache.read().await.get_valid(&key) {
+        return Ok(feed::reply(content, &conditions));
+    }
+
+    let rss = generate_new_rss(&aggregator).await?;
+    let mut cache = aggregator.cache.write().await;
+    let content = cache.insert(key, rss);
+    Ok(feed::reply(content, &conditions))
+}
+
+async fn generate_new_rss(aggregator: &Aggregator) -> Result<String, Rejection> {
+    let results = future::join_all(aggregator.sources.iter().map(|&s| aggregator.fetch(s))).await;
+
+    let mut channels = Vec::new();
+    let mut last_error = None;
+    for (&source, result) in aggregator.sources.iter().zip(results) {
+        match result {
+            Ok(channel) => channels.push((source, channel)),
+            Err(e) => {
+                warn!("skip {} in aggregate feed: {e:?}", source.name());
+                last_error = Some(e);
+            }
+        }
+    }
+    // partial results are better than nothing, fail only when every source fails
+    if let (true, Some(e)) = (channels.is_empty(), last_error) {
+        return Err(e);
+    }
+
+    // keep the extension namespaces like media: used by the items
+    let namespaces: BTreeMap<String, String> = channels
+        .iter()
+        .flat_map(|(_, c)| c.namespaces().clone())
+        .collect();
+    // the sources record their own item metrics, duplicates dropped here are not filtered by the blacklist
+    let items = merge(channels);
+
+    let names: Vec<&str> = aggregator.sources.iter().map(|s| s.name()).collect();
+    let channel = ChannelBuilder::default()
+        .title(TITLE)
+        .link(LINK)
+        .namespaces(namespaces)
+        .description(format!("Items of {} in one feed", names.join(", ")))
+        .items(items)
+        .build();
+    channel.validate().map_err(MyError::Validation)?;
+    Ok(channel.to_string())
+}
+
+const TITLE: &str = "Personal RSS";
+const LINK: &str = "https://github.com/PerfectDay20/bilibili-online-filtered-rss";
+
+/// Domain of the category naming the source of an item
+const CATEGORY_DOMAIN: &str = "source";
+
+/// Tag items with their source, drop the ones already seen by guid or link, and sort by date.
+/// Items without a date are kept at the end in source order
+fn merge(channels: Vec<(AggregateSource, Channel)>) -> Vec<Item> {
+    let mut seen = HashSet::new();
+    let mut items = Vec::new();
+    for (source, channel) in channels {
+        for mut item in channel.items {
+            let id = item
+                .guid()
+                .map(|g| g.value().to_string())
+                .or_else(|| item.link().map(str::to_string));
+            if id.is_some_and(|id| !seen.insert(id)) {
+                continue;
+            }
+
+            item.set_title(format!(
+                "[{}] {}",
+                source.name(),
+                item.title().unwrap_or_default()
+            ));
+            item.categories.push(Category {
+                name: source.name().to_string(),
+                domain: Some(CATEGORY_DOMAIN.to_string()),
+            });
+            items.push(item);
+        }
+    }
+
+    items.sort_by_key(|i| {
+        Reverse(
+            i.pub_date()
+                .and_then(|d| DateTime::parse_from_rfc2822(d).ok())
+                .map(|d| d.timestamp()),
+        )
+    });
+    items
+}
+
+#[test]
+fn merge_sources() {
+    let channel = |items: &str| {
+        Channel::read_from(format!("<rss><channel>{items}</channel></rss>").as_bytes()).unwrap()
+    };
+    let merged = merge(vec![
+        (
+            AggregateSource::Bilibili,
+            channel(
+                "<item><title>a</title><guid>https://www.bilibili.com/video/BV1</guid>\
+                 <pubDate>Sat, 11 Feb 2023 08:00:00 +0000</pubDate></item>",
+            ),
+        ),
+        (
+            AggregateSource::Ddys,
+            channel("<item><title>b</title><link>https://ddys.pro/b/</link></item>"),
+        ),
+        (
+            AggregateSource::Popular,
+            channel(
+                "<item><title>a</title><guid>https://www.bilibili.com/video/BV1</guid></item>\
+                 <item><title>c</title><guid>https://www.bilibili.com/video/BV2</guid>\
+                 <pubDate>Sat, 11 Feb 2023 09:00:00 +0000</pubDate></item>",
+            ),
+        ),
+    ]);
+
+    let titles: Vec<&str> = merged.iter().filter_map(Item::title).collect();
+    assert_eq!(titles, ["[popular] c", "[bilibili] a", "[ddys] b"]);
+    assert_eq!(merged[2].categories()[0].name(), "ddys");
+}
@@ -0,0 +1,203 @@
+use std::collections::BTreeSet;
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+
+use chrono::{TimeZone, Utc};
+use rss::validation::Validate;
+use rss::{ChannelBuilder, GuidBuilder, ImageBuilder, Item, ItemBuilder};
+use serde::{Deserialize, Deserializer};
+use tokio::sync::RwLock;
+use warp::{reject, Rejection, Reply};
+
+use crate::cache::{CacheKey, RssCache};
+use crate::error::MyError;
+use crate::feed::{self, Conditions};
+use crate::{html, metrics};
+
+const SOURCE: &str = "bilibili_bangumi";
+
+/// Query of GET /bilibili/bangumi, every season of the kind by default
+#[derive(Debug, Deserialize, Default)]
+#[serde(default)]
+pub struct BangumiQuery {
+    #[serde(rename = "type")]
+    kind: BangumiType,
+    /// Comma separated season ids to follow, e.g. 1,2, empty when every season is followed
+    #[serde(deserialize_with = "season_ids")]
+    seasons: BTreeSet<u64>,
+}
+
+/// Reject the query when an id is invalid, instead of silently following every season
+fn season_ids<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BTreeSet<u64>, D::Error> {
+    String::deserialize(deserializer)?
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|s| {
+            s.parse()
+                .map_err(|_| serde::de::Error::custom(format!("invalid season id: {s}")))
+        })
+        .collect()
+}
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum BangumiType {
+    /// 番剧
+    #[default]
+    Anime,
+    /// 国创
+    Guochuang,
+}
+
+impl BangumiQuery {
+    /// types of the timeline API
+    fn types(&self) -> u8 {
+        match self.kind {
+            BangumiType::Anime => 1,
+            BangumiType::Guochuang => 4,
+        }
+    }
+}
+
+impl Display for BangumiQuery {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let seasons: Vec<String> = self.seasons.iter().map(u64::to_string).collect();
+        write!(f, "types={}&seasons={}", self.types(), seasons.join(","))
+    }
+}
+
+/// The timeline API uses result instead of data
+#[derive(Deserialize)]
+struct Timeline {
+    code: i64,
+    #[serde(default)]
+    message: String,
+    result: Option<Vec<Day>>,
+}
+
+#[derive(Deserialize)]
+struct Day {
+    episodes: Vec<Episode>,
+}
+
+#[derive(Deserialize)]
+struct Episode {
+    episode_id: u64,
+    season_id: u64,
+    /// season title
+    title: String,
+    /// e.g. 第5话
+    pub_index: String,
+    cover: String,
+    /// unix seconds
+    pub_ts: i64,
+    /// 0 when it is not released yet
+    published: u8,
+}
+
+/// GET /bilibili/bangumi?type=...&seasons=..., an item for every episode released in the last 7 days
+pub async fn generate_rss(
+    cache: Arc<RwLock<RssCache>>,
+    query: BangumiQuery,
+    conditions: Conditions,
+) -> Result<impl Reply, Rejection> {
+    let key = CacheKey::with_params(SOURCE, query.to_string());
+    if let Some(content) = cache.read().await.get_valid(&key) {
+        return Ok(feed::reply(content, &conditions));
+    }
+
+    let episodes = fetch(&query).await?;
+    let fetched = episodes.len();
+    let mut episodes: Vec<Episode> = episodes
+        .into_iter()
+        .filter(|e| e.published == 1)
+        .filter(|e| query.seasons.is_empty() || query.seasons.contains(&e.season_id))
+        .collect();
+    episodes.sort_by_key(|e| -e.pub_ts);
+    metrics::record_items(SOURCE, fetched, episodes.len());
+
+    let rss = assemble(&episodes)?;
+    let mut cache = cache.write().await;
+    let content = cache.insert(key, rss);
+    Ok(feed::reply(content, &conditions))
+}
+
+async fn fetch(query: &BangumiQuery) -> Result<Vec<Episode>, Rejection> {
+    let url = format!(
+        "https://api.bilibili.com/pgc/web/timeline?types={}&before=6&after=0",
+        query.types()
+    );
+    let timeline = metrics::record_upstream(SOURCE, async {
+        let timeline = reqwest::get(url).await?.json::<Timeline>().await?;
+        match timeline.result {
+            Some(days) if timeline.code == 0 => Ok(days),
+            _ => Err(MyError::Api {
+                code: timeline.code,
+                message: timeline.message,
+            }),
+        }
+    })
+    .await
+    .map_err(reject::custom)?;
+    Ok(timeline.into_iter().flat_map(|d| d.episodes).collect())
+}
+
+const TITLE: &str = "BiliBili bangumi timeline";
+const LINK: &str = "https://www.bilibili.com/anime/timeline/";
+const DESC: &str = "Newly released episodes of BiliBili bangumi";
+const ICON_URL: &str = "https://www.bilibili.com/favicon.ico";
+
+fn assemble(episodes: &[Episode]) -> Result<String, Rejection> {
+    let channel = ChannelBuilder::default()
+        .title(TITLE)
+        .link(LINK)
+        .description(DESC)
+        .image(Some(
+            ImageBuilder::default()
+                .title(TITLE)
+                .link(LINK)
+                .url(ICON_URL)
+                .build(),
+        ))
+        .items(episodes.iter().map(create_item).collect::<Vec<Item>>())
+        .build();
+
+    channel.validate().map_err(MyError::Validation)?;
+    Ok(channel.to_string())
+}
+
+fn create_item(e: &Episode) -> Item {
+    let link = format!("https://www.bilibili.com/bangumi/play/ep{}", e.episode_id);
+    ItemBuilder::default()
+        .title(format!("{} {}", e.title, e.pub_index))
+        .description(format!(
+            r#"<b>season:</b> {title}
+    <p></p>
+    <b>episode:</b> {index}
+    <p></p>
+    <img style="width:100%" src="{cover}" width="500">"#,
+            title = html::escape(&e.title),
+            index = html::escape(&e.pub_index),
+            cover = html::escape(&e.cover),
+        ))
+        .link(link.clone())
+        .guid(GuidBuilder::default().value(link).permalink(true).build())
+        .pub_date(
+            Utc.timestamp_opt(e.pub_ts, 0)
+                .single()
+                .map(|t| t.to_rfc2822()),
+        )
+        .build()
+}
+
+#[test]
+fn bangumi_query() {
+    let query: BangumiQuery =
+        serde_json::from_str(r#"{"type": "guochuang", "seasons": "3, 1,"}"#).unwrap();
+    assert_eq!(query.to_string(), "types=4&seasons=1,3");
+    assert!(serde_json::from_str::<BangumiQuery>(r#"{"seasons": "abc"}"#).is_err());
+    let query = BangumiQuery::default();
+    assert!(query.seasons.is_empty());
+    assert_eq!(query.to_string(), "types=1&seasons=");
+}
@@ -1,6 +1,7 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::SystemTime;
 use std::{fs, process};
 
 use serde::Deserialize;
@@ -9,29 +10,202 @@ use tokio::sync::RwLock;
 use tracing::{error, info};
 use warp::{Rejection, Reply};
 
-use crate::bilibili::BiliData;
+use crate::aggregate;
+use crate::bilibili::{zone, BiliData};
+use crate::cache::RssCache;
 
 #[derive(Debug, Serialize, Deserialize, Default)]
 #[serde(default)]
 pub struct Blacklist {
     #[serde(default = "Blacklist::default_enable")]
     enable: bool,
+    /// Author names, replaced by mids once the author is seen in the feed
     authors: HashSet<String>,
+    /// Author mid to last known author name
+    mids: HashMap<u64, String>,
     categories: HashSet<String>,
+    /// Category tids, stable when Bilibili renames a category
+    tids: HashSet<u32>,
+    /// Top level zone tids, block all categories under them
+    zones: HashSet<u32>,
+    /// The file this blacklist is loaded from and saved to
+    #[serde(skip)]
+    path: Option<PathBuf>,
+    #[serde(skip)]
+    loaded_at: Option<SystemTime>,
+    /// Changed by API since last save
+    #[serde(skip)]
+    dirty: bool,
+    /// How many items each rule has filtered out since start
+    #[serde(skip)]
+    hits: HashMap<Rule, u64>,
+}
+
+/// A single blacklist entry that can exclude an item
+#[derive(Debug, Serialize, Clone, PartialEq, Eq, Hash)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum Rule {
+    Mid(u64),
+    Author(String),
+    Category(String),
+    Tid(u32),
+    Zone(u32),
+}
+
+#[derive(Serialize)]
+pub struct RuleHits {
+    rule: Rule,
+    hits: u64,
 }
 
 impl Blacklist {
     fn default_enable() -> bool {
         true
     }
-    /// Filter rss content based on author name and category.
+
+    pub fn with_entries(
+        mid: Option<(u64, String)>,
+        author: Option<String>,
+        tid: Option<u32>,
+    ) -> Self {
+        Blacklist {
+            enable: true,
+            authors: author.into_iter().collect(),
+            mids: mid.into_iter().collect(),
+            tids: tid.into_iter().collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Where the blacklist is loaded from
+    pub fn source(&self) -> String {
+        match &self.path {
+            Some(p) => p.to_string_lossy().to_string(),
+            None => "embedded".to_string(),
+        }
+    }
+
+    pub fn loaded_at(&self) -> Option<SystemTime> {
+        self.loaded_at
+    }
+
+    /// Number of entries of all kinds
+    pub fn len(&self) -> usize {
+        self.authors.len()
+            + self.mids.len()
+            + self.categories.len()
+            + self.tids.len()
+            + self.zones.len()
+    }
+
+    /// Filter rss content based on author mid, author name, category and zone,
+    /// and count the hits of matched rules.
     /// Return true when items can be read
-    pub fn filter(&self, bili_data: &BiliData) -> bool {
-        if self.enable {
-            !self.authors.contains(&bili_data.owner.name)
-                && !self.categories.contains(&bili_data.tname)
-        } else {
-            true
+    pub fn filter(&mut self, bili_data: &BiliData) -> bool {
+        let rules = self.matches(bili_data);
+        for rule in &rules {
+            *self.hits.entry(rule.clone()).or_default() += 1;
+        }
+        rules.is_empty()
+    }
+
+    /// Return all rules excluding this item, empty when the item can be read
+    pub fn matches(&self, bili_data: &BiliData) -> Vec<Rule> {
+        let mut rules = Vec::new();
+        if !self.enable {
+            return rules;
+        }
+
+        let owner = &bili_data.owner;
+        if self.mids.contains_key(&owner.mid) {
+            rules.push(Rule::Mid(owner.mid));
+        }
+        if self.authors.contains(&owner.name) {
+            rules.push(Rule::Author(owner.name.clone()));
+        }
+        if self.categories.contains(&bili_data.tname) {
+            rules.push(Rule::Category(bili_data.tname.clone()));
+        }
+        if self.tids.contains(&bili_data.tid) {
+            rules.push(Rule::Tid(bili_data.tid));
+        }
+        if let Some(z) = zone::parent(bili_data.tid) {
+            if self.zones.contains(&z.tid) {
+                rules.push(Rule::Zone(z.tid));
+            }
+        }
+        rules
+    }
+
+    /// Hit count of every rule in the blacklist, least used first,
+    /// rules never hit are candidates to be pruned
+    pub fn rule_hits(&self) -> Vec<RuleHits> {
+        let rules = self
+            .mids
+            .keys()
+            .map(|m| Rule::Mid(*m))
+            .chain(self.authors.iter().map(|a| Rule::Author(a.clone())))
+            .chain(self.categories.iter().map(|c| Rule::Category(c.clone())))
+            .chain(self.tids.iter().map(|t| Rule::Tid(*t)))
+            .chain(self.zones.iter().map(|z| Rule::Zone(*z)));
+        let mut hits: Vec<RuleHits> = rules
+            .map(|rule| RuleHits {
+                hits: self.hits.get(&rule).copied().unwrap_or(0),
+                rule,
+            })
+            .collect();
+        hits.sort_by_key(|h| h.hits);
+        hits
+    }
+
+    /// Authors can rename themselves, so resolve author name entries to mids when they are seen,
+    /// and keep the last known name of mid entries up to date.
+    /// Return true when the blacklist is changed
+    pub fn observe(&mut self, items: &[BiliData]) -> bool {
+        let mut changed = false;
+        for d in items {
+            let owner = &d.owner;
+            // some APIs only return the mid
+            if owner.name.is_empty() {
+                continue;
+            }
+            if self.authors.remove(&owner.name) {
+                info!(
+                    "resolve blacklist author {} to mid {}",
+                    owner.name, owner.mid
+                );
+                self.mids.insert(owner.mid, owner.name.clone());
+                let hits = self.hits.remove(&Rule::Author(owner.name.clone()));
+                *self.hits.entry(Rule::Mid(owner.mid)).or_default() += hits.unwrap_or(0);
+                changed = true;
+            } else if let Some(name) = self.mids.get_mut(&owner.mid) {
+                if *name != owner.name {
+                    info!("blacklist author {} renamed to {}", name, owner.name);
+                    *name = owner.name.clone();
+                    changed = true;
+                }
+            }
+        }
+        changed
+    }
+
+    /// Write the blacklist back to the file it is loaded from, if any
+    pub fn save(&mut self) {
+        if let Some(p) = &self.path {
+            match fs::write(p, serde_json::to_string_pretty(self).unwrap()) {
+                Ok(()) => {
+                    info!("blacklist saved to: {}", p.to_str().unwrap());
+                    self.dirty = false;
+                }
+                Err(e) => error!("fail to save blacklist: {}", e.to_string()),
+            }
+        }
+    }
+
+    /// Save the changes not written yet, e.g. when saving failed, called on shutdown
+    pub fn flush(&mut self) {
+        if self.dirty {
+            self.save();
         }
     }
 }
@@ -41,9 +215,11 @@ impl From<Option<PathBuf>> for Blacklist {
         match path {
             Some(p) => {
                 info!("use blacklist at: {}", p.to_str().unwrap());
-                match fs::read_to_string(p) {
+                match fs::read_to_string(&p) {
                     Ok(s) => {
-                        let blacklist: Blacklist = serde_json::from_str(&s).unwrap();
+                        let mut blacklist: Blacklist = serde_json::from_str(&s).unwrap();
+                        blacklist.path = Some(p);
+                        blacklist.loaded_at = Some(SystemTime::now());
                         info!("init blacklist: {blacklist:?}");
                         blacklist
                     }
@@ -56,8 +232,9 @@ impl From<Option<PathBuf>> for Blacklist {
             }
             None => {
                 info!("no blacklist path provided, use default");
-                let blacklist: Blacklist =
+                let mut blacklist: Blacklist =
                     serde_json::from_str(include_str!("../../resources/blacklist.json")).unwrap();
+                blacklist.loaded_at = Some(SystemTime::now());
                 info!("init blacklist: {blacklist:?}");
                 blacklist
             }
@@ -69,27 +246,79 @@ impl Extend<Blacklist> for Blacklist {
     fn extend<T: IntoIterator<Item = Blacklist>>(&mut self, iter: T) {
         for b in iter {
             self.authors.extend(b.authors);
+            self.mids.extend(b.mids);
             self.categories.extend(b.categories);
+            self.tids.extend(b.tids);
+            self.zones.extend(b.zones);
+            self.dirty = true;
         }
     }
 }
 
+/// Blacklist changed, drop the cached bilibili feeds and the merged feed so the next request reflects it
+pub async fn invalidate_cache(cache: Arc<RwLock<RssCache>>) {
+    cache
+        .write()
+        .await
+        .remove_matching(|k| k.is_bilibili() || k.source == aggregate::SOURCE);
+}
+
 pub async fn patch_blacklist(
     blacklist: Arc<RwLock<Blacklist>>,
+    cache: Arc<RwLock<RssCache>>,
     body: Blacklist,
 ) -> Result<impl Reply, Rejection> {
     info!("{body:?}");
     let mut b = blacklist.write().await;
     b.extend(Some(body));
+    b.save();
+    invalidate_cache(cache).await;
     Ok(format!("added: {b:?}"))
 }
 
 pub async fn put_blacklist(
     blacklist: Arc<RwLock<Blacklist>>,
+    cache: Arc<RwLock<RssCache>>,
     body: Blacklist,
 ) -> Result<impl Reply, Rejection> {
     info!("{body:?}");
     let mut b = blacklist.write().await;
-    *b = body;
+    *b = Blacklist {
+        path: b.path.take(),
+        loaded_at: b.loaded_at,
+        hits: std::mem::take(&mut b.hits),
+        dirty: true,
+        ..body
+    };
+    b.save();
+    invalidate_cache(cache).await;
     Ok(format!("replaced: {b:?}"))
 }
+
+#[test]
+fn resolve_author_to_mid() {
+    let bili: crate::bilibili::Bili =
+        serde_json::from_str(include_str!("../../resources/api-result-sample.json")).unwrap();
+    let owner = &bili.data[0].owner;
+    let mut blacklist = Blacklist::with_entries(None, Some(owner.name.clone()), None);
+
+    assert!(blacklist.observe(&bili.data));
+    assert!(blacklist.authors.is_empty());
+    assert_eq!(blacklist.mids[&owner.mid], owner.name);
+    assert!(!blacklist.filter(&bili.data[0]));
+    assert!(!blacklist.observe(&bili.data));
+}
+
+#[test]
+fn filter_by_zone() {
+    let bili: crate::bilibili::Bili =
+        serde_json::from_str(include_str!("../../resources/api-result-sample.json")).unwrap();
+    let game = bili.data.iter().find(|d| d.tid == 17).unwrap();
+    let mut blacklist = Blacklist::with_entries(None, None, None);
+    assert!(blacklist.filter(game));
+
+    blacklist.zones.insert(4);
+    assert!(!blacklist.filter(game));
+    assert_eq!(blacklist.matches(game), vec![Rule::Zone(4)]);
+    assert_eq!(blacklist.rule_hits()[0].hits, 1);
+}
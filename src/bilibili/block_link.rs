@@ -0,0 +1,223 @@
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac};
+use reqwest::Url;
+use serde::Deserialize;
+use sha2::Sha256;
+use tokio::sync::RwLock;
+use tracing::info;
+use warp::{reject, Rejection, Reply};
+
+use crate::blacklist::{invalidate_cache, Blacklist};
+use crate::cache::RssCache;
+use crate::error::MyError;
+use crate::html;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Links stay valid for a week, long enough for items to be read in a RSS reader
+const LINK_TTL: Duration = Duration::from_secs(7 * 24 * 3600);
+
+/// Sign and verify the "block this author/category" links put into feed items,
+/// so they can be clicked from a RSS reader without the auth password
+pub struct BlockLinkSigner {
+    secret: Vec<u8>,
+    base_url: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BlockQuery {
+    mid: Option<u64>,
+    author: Option<String>,
+    tid: Option<u32>,
+    /// Only for display, categories are blocked by tid because Bilibili renames them
+    category: Option<String>,
+    expires: u64,
+    sig: String,
+}
+
+impl BlockLinkSigner {
+    pub fn new(secret: String, base_url: String) -> Self {
+        Self {
+            secret: secret.into_bytes(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// Block by mid, the author name is only recorded as the last known name
+    pub fn author_link(&self, mid: u64, author: &str) -> String {
+        self.link(&[("mid", &mid.to_string()), ("author", author)])
+    }
+
+    /// Block by tid, the category name is only for display
+    pub fn category_link(&self, tid: u32, category: &str) -> String {
+        self.link(&[("tid", &tid.to_string()), ("category", category)])
+    }
+
+    fn link(&self, params: &[(&str, &str)]) -> String {
+        let expires = (SystemTime::now() + LINK_TTL)
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let sig = hex::encode(self.mac(params, expires).finalize().into_bytes());
+        let expires = expires.to_string();
+        let mut params = params.to_vec();
+        params.push(("expires", &expires));
+        params.push(("sig", &sig));
+        Url::parse_with_params(&format!("{}/bilibili/block", self.base_url), &params)
+            .unwrap()
+            .to_string()
+    }
+
+    fn mac(&self, params: &[(&str, &str)], expires: u64) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.secret).unwrap();
+        for (k, v) in params {
+            mac.update(format!("{k}\n{v}\n").as_bytes());
+        }
+        mac.update(expires.to_string().as_bytes());
+        mac
+    }
+
+    /// Check signature and expiry, return the blacklist to be added
+    fn verify(&self, query: &BlockQuery) -> Result<Blacklist, MyError> {
+        let mid = query.mid.map(|m| m.to_string());
+        let tid = query.tid.map(|t| t.to_string());
+        let params: Vec<(&str, &str)> = match (&mid, &query.author, &tid, &query.category) {
+            (Some(m), Some(a), None, None) => vec![("mid", m), ("author", a)],
+            (None, None, Some(t), Some(c)) => vec![("tid", t), ("category", c)],
+            _ => return Err(MyError::InvalidBlockLink),
+        };
+        let sig = hex::decode(&query.sig).map_err(|_| MyError::InvalidBlockLink)?;
+        self.mac(&params, query.expires)
+            .verify_slice(&sig)
+            .map_err(|_| MyError::InvalidBlockLink)?;
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        if query.expires < now {
+            return Err(MyError::BlockLinkExpired);
+        }
+
+        let mid = query.mid.zip(query.author.clone());
+        Ok(Blacklist::with_entries(mid, None, query.tid))
+    }
+}
+
+impl BlockQuery {
+    /// What the link blocks, e.g. author foo (42)
+    fn target(&self) -> String {
+        match (self.mid, &self.author, self.tid, &self.category) {
+            (Some(m), Some(a), _, _) => format!("author {a} ({m})"),
+            (_, _, Some(t), Some(c)) => format!("category {c} ({t})"),
+            _ => String::new(),
+        }
+    }
+
+    /// The signed parameters as hidden form fields
+    fn hidden_inputs(&self) -> String {
+        let params = [
+            ("mid", self.mid.map(|m| m.to_string())),
+            ("author", self.author.clone()),
+            ("tid", self.tid.map(|t| t.to_string())),
+            ("category", self.category.clone()),
+            ("expires", Some(self.expires.to_string())),
+            ("sig", Some(self.sig.clone())),
+        ];
+        params
+            .into_iter()
+            .filter_map(|(k, v)| {
+                v.map(|v| {
+                    format!(
+                        r#"<input type="hidden" name="{k}" value="{}">"#,
+                        html::escape(&v)
+                    )
+                })
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// GET /bilibili/block, a page to confirm the signed link.
+/// Nothing is blocked until it is posted, so readers and scanners prefetching the link change nothing
+pub async fn confirm(
+    signer: Option<Arc<BlockLinkSigner>>,
+    query: BlockQuery,
+) -> Result<impl Reply, Rejection> {
+    let signer = signer.ok_or_else(|| reject::custom(MyError::BlockLinkNotSet))?;
+    signer.verify(&query).map_err(reject::custom)?;
+    Ok(warp::reply::html(format!(
+        r#"<!DOCTYPE html>
+<html>
+<head><meta charset="utf-8"><meta name="viewport" content="width=device-width"><title>Block</title></head>
+<body>
+<form method="post" action="block">
+<p>Block {target}?</p>
+{inputs}
+<button type="submit">Block</button>
+</form>
+</body>
+</html>"#,
+        target = html::escape(&query.target()),
+        inputs = query.hidden_inputs()
+    )))
+}
+
+/// POST /bilibili/block, add the author or category of a signed link to the blacklist
+pub async fn block(
+    signer: Option<Arc<BlockLinkSigner>>,
+    blacklist: Arc<RwLock<Blacklist>>,
+    cache: Arc<RwLock<RssCache>>,
+    query: BlockQuery,
+) -> Result<impl Reply, Rejection> {
+    info!("{query:?}");
+    let signer = signer.ok_or_else(|| reject::custom(MyError::BlockLinkNotSet))?;
+    let added = signer.verify(&query).map_err(reject::custom)?;
+    let message = format!("blocked: {added:?}");
+
+    let mut b = blacklist.write().await;
+    b.extend(Some(added));
+    b.save();
+    invalidate_cache(cache).await;
+    Ok(message)
+}
+
+#[test]
+fn verify_signed_link() {
+    let signer = BlockLinkSigner::new("secret".to_string(), "http://localhost/".to_string());
+    let link = Url::parse(&signer.author_link(42, "some author&")).unwrap();
+    let pairs: std::collections::HashMap<_, _> = link.query_pairs().into_owned().collect();
+    let query = |mid: u64| BlockQuery {
+        mid: Some(mid),
+        author: Some(pairs["author"].clone()),
+        tid: None,
+        category: None,
+        expires: pairs["expires"].parse().unwrap(),
+        sig: pairs["sig"].clone(),
+    };
+
+    assert_eq!(pairs["author"], "some author&");
+    assert!(signer.verify(&query(42)).is_ok());
+    assert!(signer.verify(&query(43)).is_err());
+
+    let link = Url::parse(&signer.category_link(17, "单机游戏")).unwrap();
+    let pairs: std::collections::HashMap<_, _> = link.query_pairs().into_owned().collect();
+    let query = |tid: u32| BlockQuery {
+        mid: None,
+        author: None,
+        tid: Some(tid),
+        category: Some(pairs["category"].clone()),
+        expires: pairs["expires"].parse().unwrap(),
+        sig: pairs["sig"].clone(),
+    };
+    assert!(signer.verify(&query(17)).is_ok());
+    assert!(signer.verify(&query(18)).is_err());
+
+    let inputs = query(17).hidden_inputs();
+    assert!(inputs.contains(r#"name="tid" value="17""#));
+    assert!(!inputs.contains(r#"name="mid""#));
+    assert_eq!(query(17).target(), "category 单机游戏 (17)");
+}
@@ -0,0 +1,288 @@
+use std::sync::Arc;
+
+use serde::Deserialize;
+use tokio::sync::RwLock;
+use warp::{reject, Rejection, Reply};
+
+use crate::bilibili::rss_generator::{cached_feed, ChannelInfo, ItemRenderer};
+use crate::bilibili::{self, BiliData, Owner, Stat};
+use crate::blacklist::Blacklist;
+use crate::cache::{CacheKey, RssCache};
+use crate::feed::Conditions;
+use crate::metrics;
+
+const FAVORITES: &str = "bilibili_favorites";
+const SEASON: &str = "bilibili_season";
+const SERIES: &str = "bilibili_series";
+
+#[derive(Deserialize)]
+struct Favorites {
+    info: FavoritesInfo,
+    /// null when the folder is empty
+    #[serde(default)]
+    medias: Option<Vec<Media>>,
+}
+
+#[derive(Deserialize)]
+struct FavoritesInfo {
+    title: String,
+    upper: Upper,
+}
+
+#[derive(Deserialize)]
+struct Upper {
+    mid: u64,
+    name: String,
+}
+
+/// A video in a favorites folder
+#[derive(Deserialize)]
+struct Media {
+    bvid: String,
+    title: String,
+    cover: String,
+    intro: String,
+    /// seconds
+    duration: u32,
+    upper: Upper,
+    cnt_info: CountInfo,
+    /// publish time, not the time it is added to the folder
+    pubtime: i64,
+}
+
+#[derive(Deserialize)]
+struct CountInfo {
+    play: u32,
+    danmaku: u32,
+}
+
+#[derive(Deserialize)]
+struct Archives {
+    archives: Vec<Archive>,
+    /// Only returned for seasons
+    meta: Option<SeasonMeta>,
+}
+
+#[derive(Deserialize)]
+struct SeasonMeta {
+    name: String,
+    #[serde(default)]
+    description: String,
+}
+
+/// A video in a season or series, the uploader is the one of the collection
+#[derive(Deserialize)]
+struct Archive {
+    bvid: String,
+    title: String,
+    pic: String,
+    pubdate: i64,
+    /// seconds
+    duration: u32,
+    stat: ArchiveStat,
+}
+
+#[derive(Deserialize)]
+struct ArchiveStat {
+    #[serde(default)]
+    view: u32,
+    #[serde(default)]
+    danmaku: u32,
+}
+
+impl From<Media> for BiliData {
+    fn from(m: Media) -> Self {
+        BiliData {
+            bvid: m.bvid,
+            tname: String::new(),
+            tid: 0,
+            pic: m.cover,
+            title: m.title,
+            owner: Owner {
+                mid: m.upper.mid,
+                name: m.upper.name,
+            },
+            desc: m.intro,
+            stat: Stat {
+                view: m.cnt_info.play,
+                danmaku: m.cnt_info.danmaku,
+            },
+            short_link_v2: String::new(),
+            pubdate: m.pubtime,
+            duration: m.duration,
+        }
+    }
+}
+
+impl Archive {
+    /// Archives don't have the uploader name nor the category
+    fn into_bili_data(self, mid: u64) -> BiliData {
+        BiliData {
+            bvid: self.bvid,
+            tname: String::new(),
+            tid: 0,
+            pic: self.pic,
+            title: self.title,
+            owner: Owner {
+                mid,
+                name: String::new(),
+            },
+            desc: String::new(),
+            stat: Stat {
+                view: self.stat.view,
+                danmaku: self.stat.danmaku,
+            },
+            short_link_v2: String::new(),
+            pubdate: self.pubdate,
+            duration: self.duration,
+        }
+    }
+}
+
+/// GET /bilibili/favorites/{media_id}, latest videos added to a public favorites folder
+pub async fn generate_favorites(
+    media_id: u64,
+    blacklist: Arc<RwLock<Blacklist>>,
+    cache: Arc<RwLock<RssCache>>,
+    renderer: Arc<ItemRenderer>,
+    conditions: Conditions,
+) -> Result<impl Reply, Rejection> {
+    let key = CacheKey::with_params(FAVORITES, format!("media_id={media_id}"));
+    cached_feed(key, blacklist, cache, &renderer, conditions, async {
+        let url = format!(
+            "https://api.bilibili.com/x/v3/fav/resource/list?media_id={media_id}&ps=20&pn=1&order=mtime&platform=web"
+        );
+        let favorites = metrics::record_upstream(FAVORITES, bilibili::get::<Favorites>(&url))
+            .await
+            .map_err(reject::custom)?;
+        let channel = ChannelInfo {
+            title: format!("BiliBili favorites: {}", favorites.info.title),
+            link: format!(
+                "https://space.bilibili.com/{}/favlist?fid={media_id}",
+                favorites.info.upper.mid
+            ),
+            description: format!(
+                "Favorites folder {} of {}",
+                favorites.info.title, favorites.info.upper.name
+            ),
+        };
+        let items = favorites
+            .medias
+            .unwrap_or_default()
+            .into_iter()
+            .map(BiliData::from)
+            .collect();
+        Ok((channel, items))
+    })
+    .await
+}
+
+/// GET /bilibili/user/{mid}/season/{season_id}, latest videos of an uploader's collection (合集)
+pub async fn generate_season(
+    mid: u64,
+    season_id: u64,
+    blacklist: Arc<RwLock<Blacklist>>,
+    cache: Arc<RwLock<RssCache>>,
+    renderer: Arc<ItemRenderer>,
+    conditions: Conditions,
+) -> Result<impl Reply, Rejection> {
+    let key = CacheKey::with_params(SEASON, format!("mid={mid}&season_id={season_id}"));
+    cached_feed(key, blacklist, cache, &renderer, conditions, async {
+        let url = format!(
+            "https://api.bilibili.com/x/polymer/web-space/seasons_archives_list?mid={mid}&season_id={season_id}&sort_reverse=true&page_num=1&page_size=30"
+        );
+        let archives = metrics::record_upstream(SEASON, bilibili::get::<Archives>(&url))
+            .await
+            .map_err(reject::custom)?;
+        let (name, description) = archives
+            .meta
+            .map(|m| (m.name, m.description))
+            .unwrap_or_else(|| (season_id.to_string(), String::new()));
+        let channel = ChannelInfo {
+            title: format!("BiliBili collection: {name}"),
+            link: format!(
+                "https://space.bilibili.com/{mid}/channel/collectiondetail?sid={season_id}"
+            ),
+            description: if description.is_empty() {
+                format!("Collection {name}")
+            } else {
+                description
+            },
+        };
+        Ok((channel, into_items(archives.archives, mid)))
+    })
+    .await
+}
+
+/// GET /bilibili/user/{mid}/series/{series_id}, latest videos of an uploader's series (系列)
+pub async fn generate_series(
+    mid: u64,
+    series_id: u64,
+    blacklist: Arc<RwLock<Blacklist>>,
+    cache: Arc<RwLock<RssCache>>,
+    renderer: Arc<ItemRenderer>,
+    conditions: Conditions,
+) -> Result<impl Reply, Rejection> {
+    let key = CacheKey::with_params(SERIES, format!("mid={mid}&series_id={series_id}"));
+    cached_feed(key, blacklist, cache, &renderer, conditions, async {
+        let url = format!(
+            "https://api.bilibili.com/x/series/archives?mid={mid}&series_id={series_id}&sort=desc&pn=1&ps=30"
+        );
+        let archives = metrics::record_upstream(SERIES, bilibili::get::<Archives>(&url))
+            .await
+            .map_err(reject::custom)?;
+        let channel = ChannelInfo {
+            title: format!("BiliBili series: {series_id}"),
+            link: format!(
+                "https://space.bilibili.com/{mid}/channel/seriesdetail?sid={series_id}"
+            ),
+            description: format!("Series {series_id} of BiliBili uploader {mid}"),
+        };
+        Ok((channel, into_items(archives.archives, mid)))
+    })
+    .await
+}
+
+fn into_items(archives: Vec<Archive>, mid: u64) -> Vec<BiliData> {
+    archives
+        .into_iter()
+        .map(|a| a.into_bili_data(mid))
+        .collect()
+}
+
+#[test]
+fn map_favorites() {
+    let favorites: Favorites = serde_json::from_str(
+        r#"{"info": {"title": "f", "upper": {"mid": 1, "name": "a"}},
+            "medias": [{"bvid": "BV1ph41177Mt", "type": 2, "title": "t", "cover": "https://i0.hdslb.com/a.jpg",
+            "intro": "i", "duration": 60, "upper": {"mid": 2, "name": "b"},
+            "cnt_info": {"play": 10, "danmaku": 1, "collect": 3}, "pubtime": 1676104200, "fav_time": 1676200000}]}"#,
+    )
+    .unwrap();
+    let d = BiliData::from(favorites.medias.unwrap().remove(0));
+    assert_eq!(d.owner.name, "b");
+    assert_eq!(d.pubdate, 1676104200);
+    assert_eq!(d.tid, 0);
+}
+
+#[test]
+fn render_without_category() {
+    use crate::bilibili::block_link::BlockLinkSigner;
+    use crate::bilibili::rss_generator::create_item;
+
+    let media: Media = serde_json::from_str(
+        r#"{"bvid": "BV1ph41177Mt", "title": "t", "cover": "https://i0.hdslb.com/a.jpg", "intro": "i",
+            "duration": 60, "upper": {"mid": 2, "name": "b"}, "cnt_info": {"play": 10, "danmaku": 1},
+            "pubtime": 1676104200}"#,
+    )
+    .unwrap();
+    let signer = BlockLinkSigner::new("secret".to_string(), "http://localhost".to_string());
+    let renderer = ItemRenderer::new(Some(Arc::new(signer)), false, Default::default());
+    let item = create_item(&BiliData::from(media), &renderer);
+
+    let desc = item.description().unwrap();
+    assert!(desc.contains("block author"));
+    assert!(!desc.contains("block category"));
+    assert!(!desc.contains("tid="));
+    assert!(item.categories().is_empty());
+}
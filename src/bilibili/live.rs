@@ -0,0 +1,285 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use chrono::{FixedOffset, NaiveDateTime, TimeZone, Utc};
+use rss::validation::Validate;
+use rss::{ChannelBuilder, GuidBuilder, ImageBuilder, Item, ItemBuilder};
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+use tracing::{error, info, warn};
+use warp::{Rejection, Reply};
+
+use crate::aggregate;
+use crate::bilibili::ApiResponse;
+use crate::cache::{CacheKey, RssCache};
+use crate::error::MyError;
+use crate::feed::{self, Conditions};
+use crate::status::unix_secs;
+use crate::{html, metrics};
+
+const SOURCE: &str = "bilibili_live";
+
+/// Events older than these are dropped from the feed
+const EVENT_CAPACITY: usize = 50;
+
+/// Live status of the configured rooms and the recent go-live events,
+/// saved to a file so a restart doesn't announce a room twice
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct LiveRooms {
+    /// Room id to whether it was live at the last poll
+    live: BTreeMap<u64, bool>,
+    /// Newest first
+    events: VecDeque<LiveEvent>,
+    #[serde(skip)]
+    rooms: Vec<u64>,
+    #[serde(skip)]
+    path: Option<PathBuf>,
+    /// Changed since last save
+    #[serde(skip)]
+    dirty: bool,
+}
+
+/// A room going from offline to online
+#[derive(Serialize, Deserialize, Clone)]
+struct LiveEvent {
+    room_id: u64,
+    uid: u64,
+    title: String,
+    cover: String,
+    area: String,
+    /// unix seconds
+    live_time: i64,
+}
+
+#[derive(Deserialize)]
+struct RoomInfo {
+    room_id: u64,
+    uid: u64,
+    title: String,
+    user_cover: String,
+    /// 0 offline, 1 live, 2 playing recorded videos
+    live_status: u8,
+    /// e.g. 2023-02-11 20:00:00 in UTC+8, or 0000-00-00 00:00:00 when offline
+    live_time: String,
+    parent_area_name: String,
+    area_name: String,
+}
+
+impl LiveRooms {
+    /// Load the state of the last run from path if it exists
+    pub fn new(rooms: Vec<u64>, path: Option<PathBuf>) -> Self {
+        let mut live_rooms = match path.as_ref().filter(|p| p.exists()) {
+            Some(p) => {
+                info!("use live room state at: {}", p.to_str().unwrap());
+                fs::read_to_string(p)
+                    .map_err(|e| e.to_string())
+                    .and_then(|s| serde_json::from_str(&s).map_err(|e| e.to_string()))
+                    .unwrap_or_else(|e| {
+                        error!("fail to read live room state file: {e}");
+                        process::exit(1);
+                    })
+            }
+            None => LiveRooms::default(),
+        };
+        live_rooms.rooms = rooms;
+        live_rooms.path = path;
+        live_rooms
+    }
+
+    /// No room to poll
+    pub fn is_empty(&self) -> bool {
+        self.rooms.is_empty()
+    }
+
+    /// Record the status of a room, return true when it just went live
+    fn observe(&mut self, info: &RoomInfo, room_id: u64) -> bool {
+        let live = info.live_status == 1;
+        let was_live = self.live.insert(room_id, live).unwrap_or(false);
+        if was_live != live {
+            self.dirty = true;
+        }
+        if !live || was_live {
+            return false;
+        }
+
+        self.events.push_front(LiveEvent {
+            room_id: info.room_id,
+            uid: info.uid,
+            title: info.title.clone(),
+            cover: info.user_cover.clone(),
+            area: format!("{} · {}", info.parent_area_name, info.area_name),
+            live_time: parse_live_time(&info.live_time)
+                .unwrap_or_else(|| unix_secs(SystemTime::now()) as i64),
+        });
+        self.events.truncate(EVENT_CAPACITY);
+        true
+    }
+
+    pub fn save(&mut self) {
+        if let Some(p) = &self.path {
+            match fs::write(p, serde_json::to_string_pretty(self).unwrap()) {
+                Ok(()) => self.dirty = false,
+                Err(e) => error!("fail to save live room state: {}", e.to_string()),
+            }
+        }
+    }
+
+    /// Save the state changed since the last poll, called on shutdown
+    pub fn flush(&mut self) {
+        if self.dirty {
+            self.save();
+        }
+    }
+}
+
+/// live_time is in China Standard Time
+fn parse_live_time(s: &str) -> Option<i64> {
+    let time = NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").ok()?;
+    FixedOffset::east_opt(8 * 3600)?
+        .from_local_datetime(&time)
+        .single()
+        .map(|t| t.timestamp())
+}
+
+async fn fetch(room_id: u64) -> Result<RoomInfo, MyError> {
+    metrics::record_upstream(SOURCE, async {
+        reqwest::get(format!(
+            "https://api.live.bilibili.com/room/v1/Room/get_info?room_id={room_id}"
+        ))
+        .await?
+        .json::<ApiResponse<RoomInfo>>()
+        .await?
+        .into_data()
+    })
+    .await
+}
+
+/// Poll every room forever, and drop the cached feed when a room goes live
+pub async fn poll(
+    live_rooms: Arc<RwLock<LiveRooms>>,
+    cache: Arc<RwLock<RssCache>>,
+    interval: Duration,
+) {
+    let rooms = live_rooms.read().await.rooms.clone();
+    info!("poll live rooms {rooms:?} every {interval:?}");
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        let mut went_live = false;
+        for &room_id in &rooms {
+            match fetch(room_id).await {
+                Ok(info) => {
+                    if live_rooms.write().await.observe(&info, room_id) {
+                        info!("live room {room_id} goes live: {}", info.title);
+                        went_live = true;
+                    }
+                }
+                Err(e) => warn!("fail to get live room {room_id}: {e}"),
+            }
+        }
+
+        live_rooms.write().await.flush();
+        if went_live {
+            cache
+                .write()
+                .await
+                .remove_matching(|k| k.source == SOURCE || k.source == aggregate::SOURCE);
+        }
+    }
+}
+
+/// GET /bilibili/live, an item every time a configured room goes live
+pub async fn generate_rss(
+    live_rooms: Arc<RwLock<LiveRooms>>,
+    cache: Arc<RwLock<RssCache>>,
+    conditions: Conditions,
+) -> Result<impl Reply, Rejection> {
+    let key = CacheKey::new(SOURCE);
+    if let Some(content) = cache.read().await.get_valid(&key) {
+        return Ok(feed::reply(content, &conditions));
+    }
+
+    let rss = assemble(&live_rooms.read().await.events)?;
+    let mut cache = cache.write().await;
+    let content = cache.insert(key, rss);
+    Ok(feed::reply(content, &conditions))
+}
+
+const TITLE: &str = "BiliBili live rooms";
+const LINK: &str = "https://live.bilibili.com";
+const DESC: &str = "Notifications when the followed BiliBili live rooms go live";
+const ICON_URL: &str = "https://www.bilibili.com/favicon.ico";
+
+fn assemble<'a>(events: impl IntoIterator<Item = &'a LiveEvent>) -> Result<String, Rejection> {
+    let channel = ChannelBuilder::default()
+        .title(TITLE)
+        .link(LINK)
+        .description(DESC)
+        .image(Some(
+            ImageBuilder::default()
+                .title(TITLE)
+                .link(LINK)
+                .url(ICON_URL)
+                .build(),
+        ))
+        .items(events.into_iter().map(create_item).collect::<Vec<Item>>())
+        .build();
+
+    channel.validate().map_err(MyError::Validation)?;
+    Ok(channel.to_string())
+}
+
+fn create_item(e: &LiveEvent) -> Item {
+    let link = format!("https://live.bilibili.com/{}", e.room_id);
+    ItemBuilder::default()
+        .title(format!("[live] {}", e.title))
+        .description(format!(
+            r#"<b>area:</b> {area}
+    <p></p>
+    <img style="width:100%" src="{cover}" width="500">"#,
+            area = html::escape(&e.area),
+            cover = html::escape(&e.cover),
+        ))
+        .link(link)
+        .guid(
+            // the same live session has the same start time, even across restarts
+            GuidBuilder::default()
+                .value(format!("bilibili-live-{}-{}", e.room_id, e.live_time))
+                .permalink(false)
+                .build(),
+        )
+        .pub_date(
+            Utc.timestamp_opt(e.live_time, 0)
+                .single()
+                .map(|t| t.to_rfc2822()),
+        )
+        .build()
+}
+
+#[test]
+fn go_live_once() {
+    let info: ApiResponse<RoomInfo> = serde_json::from_str(
+        r#"{"code": 0, "message": "0", "data": {"room_id": 5440, "uid": 9617619, "title": "t",
+            "user_cover": "https://i0.hdslb.com/a.jpg", "live_status": 1, "live_time": "2023-02-11 20:00:00",
+            "parent_area_name": "网游", "area_name": "英雄联盟"}}"#,
+    )
+    .unwrap();
+    let mut info = info.into_data().unwrap();
+    let mut rooms = LiveRooms::new(vec![5440], None);
+
+    assert!(rooms.observe(&info, 5440));
+    assert!(!rooms.observe(&info, 5440));
+    assert_eq!(rooms.events[0].live_time, 1676116800);
+
+    info.live_status = 0;
+    assert!(!rooms.observe(&info, 5440));
+    assert_eq!(rooms.events.len(), 1);
+    assert!(assemble(&rooms.events)
+        .unwrap()
+        .contains("bilibili-live-5440-1676116800"));
+}
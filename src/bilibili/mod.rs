@@ -1,32 +1,178 @@
+pub mod bangumi;
 pub mod blacklist;
+pub mod block_link;
+pub mod collection;
+pub mod live;
+pub mod popular;
+pub mod ranking;
 pub mod rss_generator;
+pub mod search;
+pub mod title_history;
+pub mod user;
+mod wbi;
+pub mod zone;
 
-use serde::Deserialize;
+use std::sync::{LazyLock, Mutex};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer, Serialize};
+use tracing::info;
+
+use crate::error::MyError;
+
+const USER_AGENT: &str =
+    "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0 Safari/537.36";
+
+/// Newer APIs, like the WBI signed ones, reject requests without browser like headers
+static CLIENT: LazyLock<reqwest::Client> = LazyLock::new(|| {
+    reqwest::Client::builder()
+        .user_agent(USER_AGENT)
+        .default_headers(reqwest::header::HeaderMap::from_iter([(
+            reqwest::header::REFERER,
+            "https://www.bilibili.com/".parse().unwrap(),
+        )]))
+        .build()
+        .unwrap()
+});
+
+/// Device id cookie a browser gets on the first visit, APIs like search reject requests without it
+static BUVID3: LazyLock<Mutex<Option<String>>> = LazyLock::new(|| Mutex::new(None));
+
+/// Code of a request rejected for the missing or unknown buvid3
+const BUVID3_REJECTED: i64 = -412;
+
+#[derive(Deserialize)]
+struct Spi {
+    b_3: String,
+}
+
+async fn buvid3() -> Result<String, MyError> {
+    if let Some(buvid3) = BUVID3.lock().unwrap().as_ref() {
+        return Ok(buvid3.clone());
+    }
+
+    info!("fetch buvid3");
+    let spi = CLIENT
+        .get("https://api.bilibili.com/x/frontend/finger/spi")
+        .send()
+        .await?
+        .json::<ApiResponse<Spi>>()
+        .await?
+        .into_data()?;
+    *BUVID3.lock().unwrap() = Some(spi.b_3.clone());
+    Ok(spi.b_3)
+}
+
+/// Call an API with browser like headers and the buvid3 cookie
+async fn send<T: DeserializeOwned>(url: &str) -> Result<ApiResponse<T>, MyError> {
+    let cookie = format!("buvid3={}", buvid3().await?);
+    let response = CLIENT
+        .get(url)
+        .header(reqwest::header::COOKIE, cookie)
+        .send()
+        .await?
+        .json::<ApiResponse<T>>()
+        .await?;
+    if response.code == BUVID3_REJECTED {
+        info!("buvid3 rejected, fetch a new one next time");
+        *BUVID3.lock().unwrap() = None;
+    }
+    Ok(response)
+}
+
+/// Call an API that needs no signing and return its data
+pub async fn get<T: DeserializeOwned>(url: &str) -> Result<T, MyError> {
+    send(url).await?.into_data()
+}
+
+/// Response of most bilibili APIs, data is null when code is not 0
+#[derive(Deserialize)]
+pub struct ApiResponse<T> {
+    pub code: i64,
+    #[serde(default)]
+    pub message: String,
+    pub data: Option<T>,
+}
+
+impl<T> ApiResponse<T> {
+    pub fn into_data(self) -> Result<T, MyError> {
+        match self.data {
+            Some(data) if self.code == 0 => Ok(data),
+            _ => Err(MyError::Api {
+                code: self.code,
+                message: self.message,
+            }),
+        }
+    }
+}
 
 #[derive(Deserialize)]
 pub struct Bili {
     pub data: Vec<BiliData>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize, Clone)]
 pub struct BiliData {
+    /// stable video id, e.g. BV1ph41177Mt
+    pub bvid: String,
     /// category
     pub tname: String,
+    /// category id
+    pub tid: u32,
     pub pic: String,
     pub title: String,
     pub owner: Owner,
     pub desc: String,
     pub stat: Stat,
+    /// not returned by every API
+    #[serde(default)]
     pub short_link_v2: String,
+    /// unix seconds
+    pub pubdate: i64,
+    /// seconds
+    pub duration: u32,
 }
 
-#[derive(Deserialize)]
+impl BiliData {
+    /// The short link if there is one, or the full video URL
+    pub fn link(&self) -> String {
+        if self.short_link_v2.is_empty() {
+            format!("https://www.bilibili.com/video/{}", self.bvid)
+        } else {
+            self.short_link_v2.clone()
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone)]
 pub struct Owner {
+    pub mid: u64,
     pub name: String,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize, Clone)]
 pub struct Stat {
     pub view: u32,
     pub danmaku: u32,
 }
+
+/// A number that some APIs return as a string, like "17", or "--" when hidden
+pub fn lenient_u32<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Number {
+        Number(u32),
+        Text(String),
+    }
+    Ok(match Number::deserialize(deserializer)? {
+        Number::Number(n) => n,
+        Number::Text(s) => s.parse().unwrap_or(0),
+    })
+}
+
+/// Seconds of MM:SS or HH:MM:SS
+pub fn parse_length(length: &str) -> u32 {
+    length
+        .split(':')
+        .fold(0, |secs, part| secs * 60 + part.parse::<u32>().unwrap_or(0))
+}
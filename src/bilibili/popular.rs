@@ -0,0 +1,164 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use serde::Deserialize;
+use tokio::sync::RwLock;
+use warp::{reject, Rejection, Reply};
+
+use crate::bilibili::rss_generator::{cached_feed, ChannelInfo, ItemRenderer};
+use crate::bilibili::{ApiResponse, BiliData};
+use crate::blacklist::Blacklist;
+use crate::cache::{CacheKey, RssCache};
+use crate::error::MyError;
+use crate::feed::Conditions;
+use crate::metrics;
+
+const POPULAR: &str = "bilibili_popular";
+const WEEKLY: &str = "bilibili_weekly";
+
+/// 20 items a page
+const POPULAR_PAGES: u32 = 5;
+/// Latest issues of the weekly series, a video can be in more than one
+const WEEKLY_ISSUES: usize = 2;
+
+#[derive(Deserialize)]
+struct Page {
+    list: Vec<BiliData>,
+    /// true on the last page
+    #[serde(default)]
+    no_more: bool,
+}
+
+#[derive(Deserialize)]
+struct SeriesList {
+    /// Latest issue first
+    list: Vec<Issue>,
+}
+
+#[derive(Deserialize)]
+struct Issue {
+    number: u32,
+}
+
+/// GET /bilibili/popular, the first pages of the popular list filtered by the blacklist
+pub async fn generate_popular(
+    blacklist: Arc<RwLock<Blacklist>>,
+    cache: Arc<RwLock<RssCache>>,
+    renderer: Arc<ItemRenderer>,
+    conditions: Conditions,
+) -> Result<impl Reply, Rejection> {
+    cached_feed(
+        CacheKey::new(POPULAR),
+        blacklist,
+        cache,
+        &renderer,
+        conditions,
+        async {
+            let channel = ChannelInfo {
+                title: "Filtered BiliBili popular".to_string(),
+                link: "https://www.bilibili.com/v/popular/all".to_string(),
+                description: "A filtered BiliBili popular list based on my blacklist".to_string(),
+            };
+            Ok((channel, fetch_popular().await?))
+        },
+    )
+    .await
+}
+
+/// GET /bilibili/weekly, the latest issues of the weekly must-watch series filtered by the blacklist
+pub async fn generate_weekly(
+    blacklist: Arc<RwLock<Blacklist>>,
+    cache: Arc<RwLock<RssCache>>,
+    renderer: Arc<ItemRenderer>,
+    conditions: Conditions,
+) -> Result<impl Reply, Rejection> {
+    cached_feed(
+        CacheKey::new(WEEKLY),
+        blacklist,
+        cache,
+        &renderer,
+        conditions,
+        async {
+            let channel = ChannelInfo {
+                title: "Filtered BiliBili weekly must-watch".to_string(),
+                link: "https://www.bilibili.com/v/popular/weekly".to_string(),
+                description: "A filtered BiliBili weekly must-watch series based on my blacklist"
+                    .to_string(),
+            };
+            Ok((channel, fetch_weekly().await?))
+        },
+    )
+    .await
+}
+
+async fn fetch_popular() -> Result<Vec<BiliData>, Rejection> {
+    let items = metrics::record_upstream(POPULAR, async {
+        let mut items = Vec::new();
+        for pn in 1..=POPULAR_PAGES {
+            let page = reqwest::get(format!(
+                "https://api.bilibili.com/x/web-interface/popular?ps=20&pn={pn}"
+            ))
+            .await?
+            .json::<ApiResponse<Page>>()
+            .await?
+            .into_data()?;
+            items.extend(page.list);
+            if page.no_more {
+                break;
+            }
+        }
+        Ok::<_, MyError>(items)
+    })
+    .await
+    .map_err(reject::custom)?;
+    // the list changes while paging, so an item can be on two pages
+    Ok(dedup(items))
+}
+
+async fn fetch_weekly() -> Result<Vec<BiliData>, Rejection> {
+    let items = metrics::record_upstream(WEEKLY, async {
+        let series = reqwest::get("https://api.bilibili.com/x/web-interface/popular/series/list")
+            .await?
+            .json::<ApiResponse<SeriesList>>()
+            .await?
+            .into_data()?;
+        let mut items = Vec::new();
+        for issue in series.list.iter().take(WEEKLY_ISSUES) {
+            let page = reqwest::get(format!(
+                "https://api.bilibili.com/x/web-interface/popular/series/one?number={}",
+                issue.number
+            ))
+            .await?
+            .json::<ApiResponse<Page>>()
+            .await?
+            .into_data()?;
+            items.extend(page.list);
+        }
+        Ok::<_, MyError>(items)
+    })
+    .await
+    .map_err(reject::custom)?;
+    Ok(dedup(items))
+}
+
+/// Keep the first item of every bvid
+pub fn dedup(items: Vec<BiliData>) -> Vec<BiliData> {
+    let mut seen = HashSet::new();
+    items
+        .into_iter()
+        .filter(|d| seen.insert(d.bvid.clone()))
+        .collect()
+}
+
+#[test]
+fn dedup_by_bvid() {
+    let mut bili: crate::bilibili::Bili =
+        serde_json::from_str(include_str!("../../resources/api-result-sample.json")).unwrap();
+    let len = bili.data.len();
+    let mut again: crate::bilibili::Bili =
+        serde_json::from_str(include_str!("../../resources/api-result-sample.json")).unwrap();
+    bili.data.append(&mut again.data);
+
+    let items = dedup(bili.data);
+    assert_eq!(items.len(), len);
+}
@@ -0,0 +1,129 @@
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+
+use serde::{Deserialize, Deserializer};
+use tokio::sync::RwLock;
+use warp::{reject, Rejection, Reply};
+
+use crate::bilibili::rss_generator::{cached_feed, ChannelInfo, ItemRenderer};
+use crate::bilibili::{zone, ApiResponse, BiliData};
+use crate::blacklist::Blacklist;
+use crate::cache::{CacheKey, RssCache};
+use crate::feed::Conditions;
+use crate::metrics;
+
+const SOURCE: &str = "bilibili_ranking";
+
+/// Query of GET /bilibili/ranking, the whole site ranking by default
+#[derive(Debug, Deserialize, Default)]
+#[serde(default)]
+pub struct RankingQuery {
+    /// Top level zone tid, 0 for all zones
+    #[serde(deserialize_with = "top_level_zone")]
+    rid: u32,
+    #[serde(rename = "type")]
+    kind: RankingType,
+}
+
+/// Reject the query when the zone is unknown, instead of titling it as the whole site ranking
+fn top_level_zone<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    let rid = u32::deserialize(deserializer)?;
+    if rid == 0 || zone::find(rid).is_some_and(|z| z.parent == 0) {
+        Ok(rid)
+    } else {
+        Err(serde::de::Error::custom(format!(
+            "invalid rid: {rid}, not a top level zone"
+        )))
+    }
+}
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum RankingType {
+    #[default]
+    All,
+    /// Original videos only
+    Origin,
+    /// Videos of new uploaders
+    Rookie,
+}
+
+impl RankingType {
+    fn as_str(&self) -> &'static str {
+        match self {
+            RankingType::All => "all",
+            RankingType::Origin => "origin",
+            RankingType::Rookie => "rookie",
+        }
+    }
+}
+
+impl Display for RankingQuery {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "rid={}&type={}", self.rid, self.kind.as_str())
+    }
+}
+
+#[derive(Deserialize)]
+struct RankingData {
+    list: Vec<BiliData>,
+}
+
+/// GET /bilibili/ranking?rid=...&type=..., daily top videos filtered by the blacklist
+pub async fn generate_rss(
+    blacklist: Arc<RwLock<Blacklist>>,
+    cache: Arc<RwLock<RssCache>>,
+    renderer: Arc<ItemRenderer>,
+    query: RankingQuery,
+    conditions: Conditions,
+) -> Result<impl Reply, Rejection> {
+    let key = CacheKey::with_params(SOURCE, query.to_string());
+    cached_feed(key, blacklist, cache, &renderer, conditions, async {
+        let items = fetch(&query).await?;
+        Ok((channel_info(&query), items))
+    })
+    .await
+}
+
+async fn fetch(query: &RankingQuery) -> Result<Vec<BiliData>, Rejection> {
+    let url = format!("https://api.bilibili.com/x/web-interface/ranking/v2?{query}");
+    let data = metrics::record_upstream(SOURCE, async {
+        reqwest::get(url)
+            .await?
+            .json::<ApiResponse<RankingData>>()
+            .await?
+            .into_data()
+    })
+    .await
+    .map_err(reject::custom)?;
+    Ok(data.list)
+}
+
+fn channel_info(query: &RankingQuery) -> ChannelInfo {
+    let zone = zone::find(query.rid).map_or("全站", |z| z.name);
+    let kind = match query.kind {
+        RankingType::All => "",
+        RankingType::Origin => " 原创",
+        RankingType::Rookie => " 新人",
+    };
+    ChannelInfo {
+        title: format!("Filtered BiliBili ranking: {zone}{kind}"),
+        link: "https://www.bilibili.com/v/popular/rank/all".to_string(),
+        description: "A filtered BiliBili daily ranking based on my blacklist".to_string(),
+    }
+}
+
+#[test]
+fn ranking_query() {
+    let query: RankingQuery = serde_json::from_str(r#"{"rid": 4, "type": "origin"}"#).unwrap();
+    assert_eq!(query.to_string(), "rid=4&type=origin");
+    assert_eq!(
+        channel_info(&query).title,
+        "Filtered BiliBili ranking: 游戏 原创"
+    );
+    let query: RankingQuery = serde_json::from_str("{}").unwrap();
+    assert_eq!(query.to_string(), "rid=0&type=all");
+    assert!(serde_json::from_str::<RankingQuery>(r#"{"rid": 99999}"#).is_err());
+    // a sub zone, not accepted by the ranking API
+    assert!(serde_json::from_str::<RankingQuery>(r#"{"rid": 17}"#).is_err());
+}
@@ -1,58 +1,205 @@
-use std::sync::Arc;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
 
+use chrono::{TimeZone, Utc};
+use rss::extension::dublincore::DublinCoreExtensionBuilder;
+use rss::extension::itunes::ITunesItemExtensionBuilder;
+use rss::extension::ExtensionBuilder;
 use rss::validation::Validate;
-use rss::{ChannelBuilder, GuidBuilder, ImageBuilder, Item, ItemBuilder};
+use rss::{CategoryBuilder, ChannelBuilder, Guid, GuidBuilder, ImageBuilder, Item, ItemBuilder};
+use serde::Serialize;
 use tokio::sync::RwLock;
 use tracing::info;
+use warp::reply::Response;
 use warp::{Rejection, Reply};
 
-use crate::bilibili::{Bili, BiliData};
-use crate::blacklist::Blacklist;
-use crate::cache::{CacheType, RssCache};
+use crate::bilibili::block_link::BlockLinkSigner;
+use crate::bilibili::title_history::TitleHistory;
+use crate::bilibili::{zone, Bili, BiliData};
+use crate::blacklist::{Blacklist, Rule, RuleHits};
+use crate::cache::{CacheKey, RssCache};
 use crate::error::MyError;
+use crate::feed::{self, Conditions};
+use crate::html;
+use crate::metrics;
+use crate::template::Templates;
+
+/// Name of the online list cache and the description template of every bilibili feed
+pub const SOURCE: &str = "bilibili";
+
+/// How bilibili videos are turned into rss items
+pub struct ItemRenderer {
+    signer: Option<Arc<BlockLinkSigner>>,
+    /// Use title + short link as guid like older versions, so existing subscriptions don't see every item again
+    legacy_guid: bool,
+    titles: Mutex<TitleHistory>,
+    templates: Arc<Templates>,
+    /// Unfiltered items of the last online list fetch, for the debug route
+    online_items: Mutex<Vec<BiliData>>,
+}
+
+impl ItemRenderer {
+    pub fn new(
+        signer: Option<Arc<BlockLinkSigner>>,
+        legacy_guid: bool,
+        templates: Arc<Templates>,
+    ) -> Self {
+        ItemRenderer {
+            signer,
+            legacy_guid,
+            titles: Mutex::new(TitleHistory::default()),
+            templates,
+            online_items: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+/// Title, link and description of a bilibili feed
+pub struct ChannelInfo {
+    pub title: String,
+    pub link: String,
+    pub description: String,
+}
 
 /// First get rss content from cache, if None or expired, call API
 pub async fn generate_rss(
     blacklist: Arc<RwLock<Blacklist>>,
     cache: Arc<RwLock<RssCache>>,
+    renderer: Arc<ItemRenderer>,
+    conditions: Conditions,
 ) -> Result<impl Reply, Rejection> {
-    if let Some(content) = cache.read().await.get(&CacheType::Bilibili) {
-        if !content.is_expired() {
-            info!("Cache is not expired, return cache content");
-            return Ok(reply(content.get_rss()));
-        }
+    cached_feed(
+        CacheKey::new(SOURCE),
+        blacklist,
+        cache,
+        &renderer,
+        conditions,
+        async {
+            let channel = ChannelInfo {
+                title: TITLE.to_string(),
+                link: LINK.to_string(),
+                description: DESC.to_string(),
+            };
+            let items = fetch().await?.data;
+            *renderer.online_items.lock().unwrap() = items.clone();
+            Ok((channel, items))
+        },
+    )
+    .await
+}
+
+/// Return the cached feed if it is not expired,
+/// otherwise fetch the items, filter them by the blacklist and cache the new feed.
+/// Shared by every bilibili feed
+pub async fn cached_feed(
+    key: CacheKey,
+    blacklist: Arc<RwLock<Blacklist>>,
+    cache: Arc<RwLock<RssCache>>,
+    renderer: &ItemRenderer,
+    conditions: Conditions,
+    fetch: impl Future<Output = Result<(ChannelInfo, Vec<BiliData>), Rejection>>,
+) -> Result<Response, Rejection> {
+    if let Some(content) = cache.read().await.get_valid(&key) {
+        info!("Cache is not expired, return cache content");
+        return Ok(feed::reply(content, &conditions));
     }
 
     info!("Cache is None or expired, call API to generate rss");
-    match generate_new_rss(blacklist).await {
-        Ok(rss) => {
-            cache.write().await.insert(CacheType::Bilibili, rss.clone());
-            Ok(reply(rss))
-        }
-        Err(e) => Err(e),
-    }
+    let (channel, items) = fetch.await?;
+    let rss = generate_new_rss(key.source, &channel, items, blacklist, renderer).await?;
+    let mut cache = cache.write().await;
+    let content = cache.insert(key, rss);
+    Ok(feed::reply(content, &conditions))
 }
 
-async fn generate_new_rss(blacklist: Arc<RwLock<Blacklist>>) -> Result<String, Rejection> {
-    let resp = reqwest::get("https://api.bilibili.com/x/web-interface/online/list")
-        .await
-        .map_err(MyError::Reqwest)?
-        .json::<Bili>()
-        .await
-        .map_err(MyError::Reqwest)?;
+async fn fetch() -> Result<Bili, Rejection> {
+    let resp = metrics::record_upstream(SOURCE, async {
+        reqwest::get("https://api.bilibili.com/x/web-interface/online/list")
+            .await?
+            .json::<Bili>()
+            .await
+    })
+    .await
+    .map_err(MyError::Reqwest)?;
+    Ok(resp)
+}
 
-    let b = blacklist.read().await;
-    let items: Vec<BiliData> = resp
-        .data
+async fn generate_new_rss(
+    source: &str,
+    channel: &ChannelInfo,
+    items: Vec<BiliData>,
+    blacklist: Arc<RwLock<Blacklist>>,
+    renderer: &ItemRenderer,
+) -> Result<String, Rejection> {
+    let mut b = blacklist.write().await;
+    if b.observe(&items) {
+        b.save();
+    }
+    renderer.titles.lock().unwrap().observe(&items);
+    let fetched = items.len();
+    let items: Vec<BiliData> = items
         .into_iter()
         .filter(move |bili_data| b.filter(bili_data))
         .collect();
+    metrics::record_items(source, fetched, items.len());
+
+    assemble(channel, items, renderer)
+}
+
+#[derive(Serialize)]
+struct DebugItem {
+    title: String,
+    link: String,
+    author: String,
+    mid: u64,
+    category: String,
+    tid: u32,
+    /// Empty when the item is kept in the feed
+    excluded_by: Vec<Rule>,
+}
 
-    assemble(items)
+#[derive(Serialize)]
+struct DebugFeed {
+    items: Vec<DebugItem>,
+    rule_hits: Vec<RuleHits>,
 }
 
-fn reply(rss: String) -> impl Reply {
-    warp::reply::with_header(rss, "content-type", "text/xml; charset=utf-8")
+/// Return every item of the online list with the blacklist rules excluding it,
+/// and the hit count of each rule since start.
+/// The items come from the cached online feed, so the API is called no more often than for the feed
+pub async fn generate_debug(
+    blacklist: Arc<RwLock<Blacklist>>,
+    cache: Arc<RwLock<RssCache>>,
+    renderer: Arc<ItemRenderer>,
+) -> Result<impl Reply, Rejection> {
+    generate_rss(
+        Arc::clone(&blacklist),
+        cache,
+        Arc::clone(&renderer),
+        Conditions::default(),
+    )
+    .await?;
+    let online_items = renderer.online_items.lock().unwrap().clone();
+
+    let b = blacklist.read().await;
+    let items = online_items
+        .into_iter()
+        .map(|d| DebugItem {
+            excluded_by: b.matches(&d),
+            title: d.title,
+            link: d.short_link_v2,
+            author: d.owner.name,
+            mid: d.owner.mid,
+            category: d.tname,
+            tid: d.tid,
+        })
+        .collect();
+
+    Ok(warp::reply::json(&DebugFeed {
+        items,
+        rule_hits: b.rule_hits(),
+    }))
 }
 
 const TITLE: &str = "Filtered BiliBili online list";
@@ -60,34 +207,30 @@ const LINK: &str = "https://www.bilibili.com/video/online.html";
 const DESC: &str = "A filtered BiliBili online list based on my blacklist";
 const ICON_URL: &str = "https://www.bilibili.com/favicon.ico";
 
-fn assemble(items: Vec<BiliData>) -> Result<String, Rejection> {
+fn assemble(
+    info: &ChannelInfo,
+    items: Vec<BiliData>,
+    renderer: &ItemRenderer,
+) -> Result<String, Rejection> {
     let channel = ChannelBuilder::default()
-        .title(TITLE)
-        .link(LINK)
-        .description(DESC)
+        .title(info.title.clone())
+        .link(info.link.clone())
+        .description(info.description.clone())
         .image(Some(
             ImageBuilder::default()
-                .title(TITLE)
-                .link(LINK)
+                .title(info.title.clone())
+                .link(info.link.clone())
                 .url(ICON_URL)
                 .build(),
         ))
+        .namespaces(BTreeMap::from([(
+            "media".to_string(),
+            MEDIA_NAMESPACE.to_string(),
+        )]))
         .items(
             items
                 .iter()
-                .map(|d| {
-                    ItemBuilder::default()
-                        .title(d.title.clone())
-                        .description(create_item_desc(d))
-                        .link(d.short_link_v2.clone())
-                        .guid(
-                            GuidBuilder::default()
-                                .value(d.title.clone() + &d.short_link_v2)
-                                .permalink(false)
-                                .build(),
-                        )
-                        .build()
-                })
+                .map(|d| create_item(d, renderer))
                 .collect::<Vec<Item>>(),
         )
         .build();
@@ -96,8 +239,134 @@ fn assemble(items: Vec<BiliData>) -> Result<String, Rejection> {
     Ok(channel.to_string())
 }
 
-fn create_item_desc(d: &BiliData) -> String {
+const MEDIA_NAMESPACE: &str = "http://search.yahoo.com/mrss/";
+
+pub(super) fn create_item(d: &BiliData, renderer: &ItemRenderer) -> Item {
+    // videos of favorites and collections have no category
+    let mut categories: Vec<_> = Some(d.tname.clone())
+        .filter(|n| !n.is_empty())
+        .map(|n| CategoryBuilder::default().name(n).build())
+        .into_iter()
+        .collect();
+    if let Some(z) = zone::parent(d.tid).filter(|z| z.name != d.tname) {
+        categories.push(CategoryBuilder::default().name(z.name).build());
+    }
+
+    // not an enclosure, which requires the file length
+    let thumbnail = ExtensionBuilder::default()
+        .name("media:thumbnail")
+        .attrs(BTreeMap::from([("url".to_string(), d.pic.clone())]))
+        .build();
+
+    ItemBuilder::default()
+        .title(d.title.clone())
+        .description(create_item_desc(d, renderer))
+        .link(d.link())
+        .guid(create_guid(d, renderer.legacy_guid))
+        // <author> must be an email address, dc:creator takes a name
+        .dublin_core_ext(
+            Some(d.owner.name.clone())
+                .filter(|n| !n.is_empty())
+                .map(|n| {
+                    DublinCoreExtensionBuilder::default()
+                        .creators(vec![n])
+                        .build()
+                }),
+        )
+        .categories(categories)
+        .pub_date(
+            Utc.timestamp_opt(d.pubdate, 0)
+                .single()
+                .map(|t| t.to_rfc2822()),
+        )
+        .extensions(BTreeMap::from([(
+            "media".to_string(),
+            BTreeMap::from([("thumbnail".to_string(), vec![thumbnail])]),
+        )]))
+        .itunes_ext(
+            ITunesItemExtensionBuilder::default()
+                .duration(format_duration(d.duration))
+                .image(d.pic.clone())
+                .build(),
+        )
+        .build()
+}
+
+/// Format seconds to HH:MM:SS as iTunes duration
+fn format_duration(secs: u32) -> String {
     format!(
+        "{:02}:{:02}:{:02}",
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+/// bvid stays the same when the uploader edits the title
+fn create_guid(d: &BiliData, legacy: bool) -> Guid {
+    if legacy {
+        GuidBuilder::default()
+            .value(d.title.clone() + &d.short_link_v2)
+            .permalink(false)
+            .build()
+    } else {
+        GuidBuilder::default()
+            .value(format!("https://www.bilibili.com/video/{}", d.bvid))
+            .permalink(true)
+            .build()
+    }
+}
+
+/// Fields available to the description template besides the ones of BiliData
+#[derive(Serialize)]
+struct DescContext<'a> {
+    #[serde(flatten)]
+    item: &'a BiliData,
+    /// top level zone name
+    zone: Option<&'static str>,
+    view_text: String,
+    danmaku_text: String,
+    /// HH:MM:SS
+    duration_text: String,
+    /// desc escaped, with line breaks and links, use it with {{{desc_html}}}
+    desc_html: String,
+    /// the first seen title when it is edited
+    original_title: Option<String>,
+    block_author_link: Option<String>,
+    block_category_link: Option<String>,
+}
+
+fn create_item_desc(d: &BiliData, renderer: &ItemRenderer) -> String {
+    let original_title = renderer
+        .titles
+        .lock()
+        .unwrap()
+        .original_title(d)
+        .map(str::to_string);
+    // tid 0 is no category, blocking it would drop every such item
+    let block_links = renderer.signer.as_ref().map(|s| {
+        (
+            s.author_link(d.owner.mid, &d.owner.name),
+            (d.tid != 0).then(|| s.category_link(d.tid, &d.tname)),
+        )
+    });
+
+    let context = DescContext {
+        item: d,
+        zone: zone::parent(d.tid).map(|z| z.name),
+        view_text: convert_count(d.stat.view),
+        danmaku_text: convert_count(d.stat.danmaku),
+        duration_text: format_duration(d.duration),
+        desc_html: html::format_text(&d.desc),
+        original_title,
+        block_author_link: block_links.as_ref().map(|l| l.0.clone()),
+        block_category_link: block_links.as_ref().and_then(|l| l.1.clone()),
+    };
+    if let Some(desc) = renderer.templates.render(SOURCE, &context) {
+        return desc;
+    }
+
+    let mut desc = format!(
         r#"<b>author:</b> {author}
     <p></p>
     <b>category:</b> {category}
@@ -109,13 +378,35 @@ fn create_item_desc(d: &BiliData) -> String {
     <b>danmaku:</b> {danmaku}
     <p></p>
     <img style="width:100%" src="{img_src}" width="500">"#,
-        author = d.owner.name,
-        category = d.tname,
-        desc = d.desc,
-        view = convert_count(d.stat.view),
-        danmaku = convert_count(d.stat.danmaku),
-        img_src = d.pic
-    )
+        author = html::escape(&d.owner.name),
+        category = html::escape(&d.tname),
+        desc = context.desc_html,
+        view = context.view_text,
+        danmaku = context.danmaku_text,
+        img_src = html::escape(&d.pic)
+    );
+
+    if let Some(original) = context.original_title {
+        desc = format!(
+            r#"<b>title changed, was:</b> {original}
+    <p></p>
+    {desc}"#,
+            original = html::escape(&original)
+        );
+    }
+
+    match block_links {
+        Some((author_link, category_link)) => format!(
+            r#"{desc}
+    <p></p>
+    <a href="{author_link}">block author</a>{category_link}"#,
+            author_link = html::escape(&author_link),
+            category_link = category_link
+                .map(|l| format!(r#" | <a href="{}">block category</a>"#, html::escape(&l)))
+                .unwrap_or_default()
+        ),
+        None => desc,
+    }
 }
 
 /// Convert number like view count to a easier reading format,
@@ -160,3 +451,37 @@ fn get_rss() {
     channel.validate().unwrap();
     println!("{}", channel.to_string());
 }
+
+#[test]
+fn assemble_sample() {
+    let bili: Bili =
+        serde_json::from_str(include_str!("../../resources/api-result-sample.json")).unwrap();
+    let info = ChannelInfo {
+        title: TITLE.to_string(),
+        link: LINK.to_string(),
+        description: DESC.to_string(),
+    };
+    let rss = assemble(
+        &info,
+        bili.data,
+        &ItemRenderer::new(None, false, Default::default()),
+    )
+    .unwrap();
+    let channel = rss::Channel::read_from(rss.as_bytes()).unwrap();
+    let item = &channel.items()[0];
+
+    assert!(item.pub_date().is_some());
+    assert_eq!(
+        item.dublin_core_ext().unwrap().creators(),
+        ["中国BOY超级大猩猩"]
+    );
+    assert!(item.author().is_none());
+    assert!(rss.contains("xmlns:dc="));
+    assert_eq!(item.itunes_ext().unwrap().duration(), Some("00:20:10"));
+    assert!(rss.contains("<media:thumbnail url="));
+    assert_eq!(
+        item.guid().unwrap().value(),
+        "https://www.bilibili.com/video/BV1ph41177Mt"
+    );
+    assert!(item.guid().unwrap().is_permalink());
+}
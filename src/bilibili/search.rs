@@ -0,0 +1,174 @@
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+
+use serde::Deserialize;
+use tokio::sync::RwLock;
+use warp::{reject, Rejection, Reply};
+
+use crate::bilibili::rss_generator::{cached_feed, ChannelInfo, ItemRenderer};
+use crate::bilibili::{lenient_u32, parse_length, wbi, BiliData, Owner, Stat};
+use crate::blacklist::Blacklist;
+use crate::cache::{CacheKey, RssCache};
+use crate::feed::Conditions;
+use crate::html;
+use crate::metrics;
+
+const SOURCE: &str = "bilibili_search";
+
+/// Query of GET /bilibili/search, the newest videos first by default
+#[derive(Debug, Deserialize)]
+pub struct SearchQuery {
+    keyword: String,
+    #[serde(default)]
+    order: SearchOrder,
+}
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchOrder {
+    /// Relevance
+    Totalrank,
+    Click,
+    #[default]
+    Pubdate,
+    /// Danmaku count
+    Dm,
+    /// Favorite count
+    Stow,
+}
+
+impl SearchOrder {
+    fn as_str(&self) -> &'static str {
+        match self {
+            SearchOrder::Totalrank => "totalrank",
+            SearchOrder::Click => "click",
+            SearchOrder::Pubdate => "pubdate",
+            SearchOrder::Dm => "dm",
+            SearchOrder::Stow => "stow",
+        }
+    }
+}
+
+impl Display for SearchQuery {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "keyword={}&order={}", self.keyword, self.order.as_str())
+    }
+}
+
+#[derive(Deserialize)]
+struct Search {
+    #[serde(default)]
+    result: Vec<SearchResult>,
+}
+
+/// A video in search results, title is highlighted with HTML
+#[derive(Deserialize)]
+struct SearchResult {
+    bvid: String,
+    #[serde(deserialize_with = "lenient_u32")]
+    typeid: u32,
+    typename: String,
+    /// without scheme, e.g. //i0.hdslb.com/bfs/archive/xxx.jpg
+    pic: String,
+    title: String,
+    author: String,
+    mid: u64,
+    description: String,
+    #[serde(deserialize_with = "lenient_u32")]
+    play: u32,
+    #[serde(deserialize_with = "lenient_u32")]
+    video_review: u32,
+    pubdate: i64,
+    /// MM:SS
+    duration: String,
+}
+
+impl From<SearchResult> for BiliData {
+    fn from(r: SearchResult) -> Self {
+        BiliData {
+            bvid: r.bvid,
+            tname: r.typename,
+            tid: r.typeid,
+            pic: if r.pic.starts_with("//") {
+                format!("https:{}", r.pic)
+            } else {
+                r.pic
+            },
+            title: html::strip_tags(&r.title),
+            owner: Owner {
+                mid: r.mid,
+                name: r.author,
+            },
+            desc: r.description,
+            stat: Stat {
+                view: r.play,
+                danmaku: r.video_review,
+            },
+            short_link_v2: String::new(),
+            pubdate: r.pubdate,
+            duration: parse_length(&r.duration),
+        }
+    }
+}
+
+/// GET /bilibili/search?keyword=...&order=..., first page of video search results filtered by the blacklist
+pub async fn generate_rss(
+    blacklist: Arc<RwLock<Blacklist>>,
+    cache: Arc<RwLock<RssCache>>,
+    renderer: Arc<ItemRenderer>,
+    query: SearchQuery,
+    conditions: Conditions,
+) -> Result<impl Reply, Rejection> {
+    let key = CacheKey::with_params(SOURCE, query.to_string());
+    cached_feed(key, blacklist, cache, &renderer, conditions, async {
+        let items = fetch(&query).await?;
+        let channel = ChannelInfo {
+            title: format!("Filtered BiliBili search: {}", query.keyword),
+            link: format!(
+                "https://search.bilibili.com/video?keyword={}&order={}",
+                wbi::encode(&query.keyword),
+                query.order.as_str()
+            ),
+            description: format!(
+                "BiliBili videos about {} filtered by my blacklist",
+                query.keyword
+            ),
+        };
+        Ok((channel, items))
+    })
+    .await
+}
+
+async fn fetch(query: &SearchQuery) -> Result<Vec<BiliData>, Rejection> {
+    let params = [
+        ("search_type", "video".to_string()),
+        ("keyword", query.keyword.clone()),
+        ("order", query.order.as_str().to_string()),
+        ("page", "1".to_string()),
+    ];
+    let search = metrics::record_upstream(
+        SOURCE,
+        wbi::get::<Search>(
+            "https://api.bilibili.com/x/web-interface/wbi/search/type",
+            &params,
+        ),
+    )
+    .await
+    .map_err(reject::custom)?;
+    Ok(search.result.into_iter().map(BiliData::from).collect())
+}
+
+#[test]
+fn map_search_result() {
+    let r: SearchResult = serde_json::from_str(
+        r#"{"bvid": "BV1ph41177Mt", "typeid": "17", "typename": "单机游戏", "pic": "//i0.hdslb.com/a.jpg",
+            "title": "<em class=\"keyword\">原神</em> &amp; more", "author": "a", "mid": 1, "description": "d",
+            "play": 10, "video_review": 3, "pubdate": 1676104200, "duration": "12:03"}"#,
+    )
+    .unwrap();
+    let d = BiliData::from(r);
+    assert_eq!(d.title, "原神 & more");
+    assert_eq!(d.tid, 17);
+    assert_eq!(d.pic, "https://i0.hdslb.com/a.jpg");
+    assert_eq!(d.duration, 723);
+}
@@ -0,0 +1,58 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::bilibili::BiliData;
+
+/// Videos older than this are unlikely to be in the feed again
+const CAPACITY: usize = 10000;
+
+/// The first seen title of every video, used to mark titles edited by the uploader.
+/// Only kept in memory, so titles are compared since start
+#[derive(Default)]
+pub struct TitleHistory {
+    titles: HashMap<String, String>,
+    /// bvids in insertion order, the oldest is dropped when full
+    order: VecDeque<String>,
+}
+
+impl TitleHistory {
+    /// Remember the titles of new videos
+    pub fn observe(&mut self, items: &[BiliData]) {
+        for d in items {
+            if self.titles.contains_key(&d.bvid) {
+                continue;
+            }
+            if self.order.len() >= CAPACITY {
+                if let Some(oldest) = self.order.pop_front() {
+                    self.titles.remove(&oldest);
+                }
+            }
+            self.titles.insert(d.bvid.clone(), d.title.clone());
+            self.order.push_back(d.bvid.clone());
+        }
+    }
+
+    /// The first seen title, when it is different from the current one
+    pub fn original_title(&self, d: &BiliData) -> Option<&str> {
+        self.titles
+            .get(&d.bvid)
+            .map(String::as_str)
+            .filter(|t| *t != d.title)
+    }
+}
+
+#[test]
+fn detect_title_change() {
+    let mut bili: crate::bilibili::Bili =
+        serde_json::from_str(include_str!("../../resources/api-result-sample.json")).unwrap();
+    let mut history = TitleHistory::default();
+    history.observe(&bili.data);
+    assert!(history.original_title(&bili.data[0]).is_none());
+
+    let original = bili.data[0].title.clone();
+    bili.data[0].title = "edited".to_string();
+    history.observe(&bili.data);
+    assert_eq!(
+        history.original_title(&bili.data[0]),
+        Some(original.as_str())
+    );
+}
@@ -0,0 +1,147 @@
+use std::sync::Arc;
+
+use serde::Deserialize;
+use tokio::sync::RwLock;
+use warp::{reject, Rejection, Reply};
+
+use crate::bilibili::rss_generator::{cached_feed, ChannelInfo, ItemRenderer};
+use crate::bilibili::{lenient_u32, parse_length, wbi, zone, BiliData, Owner, Stat};
+use crate::blacklist::Blacklist;
+use crate::cache::{CacheKey, RssCache};
+use crate::feed::Conditions;
+use crate::metrics;
+
+const SOURCE: &str = "bilibili_user";
+
+#[derive(Deserialize)]
+struct Search {
+    list: SearchList,
+}
+
+#[derive(Deserialize)]
+struct SearchList {
+    vlist: Vec<Submission>,
+}
+
+/// A video in the uploader's space, with different field names from BiliData
+#[derive(Deserialize)]
+struct Submission {
+    bvid: String,
+    typeid: u32,
+    pic: String,
+    title: String,
+    author: String,
+    mid: u64,
+    description: String,
+    /// "--" when hidden
+    #[serde(deserialize_with = "lenient_u32")]
+    play: u32,
+    #[serde(deserialize_with = "lenient_u32")]
+    video_review: u32,
+    created: i64,
+    /// MM:SS or HH:MM:SS
+    length: String,
+}
+
+impl From<Submission> for BiliData {
+    fn from(s: Submission) -> Self {
+        BiliData {
+            bvid: s.bvid,
+            tname: zone::find(s.typeid)
+                .map(|z| z.name.to_string())
+                .unwrap_or_default(),
+            tid: s.typeid,
+            pic: s.pic,
+            title: s.title,
+            owner: Owner {
+                mid: s.mid,
+                name: s.author,
+            },
+            desc: s.description,
+            stat: Stat {
+                view: s.play,
+                danmaku: s.video_review,
+            },
+            short_link_v2: String::new(),
+            pubdate: s.created,
+            duration: parse_length(&s.length),
+        }
+    }
+}
+
+/// GET /bilibili/user/{mid}/feed, latest videos of an uploader filtered by the blacklist
+pub async fn generate_rss(
+    mid: u64,
+    blacklist: Arc<RwLock<Blacklist>>,
+    cache: Arc<RwLock<RssCache>>,
+    renderer: Arc<ItemRenderer>,
+    conditions: Conditions,
+) -> Result<impl Reply, Rejection> {
+    cached_feed(
+        CacheKey::with_params(SOURCE, format!("mid={mid}")),
+        blacklist,
+        cache,
+        &renderer,
+        conditions,
+        async {
+            let items = fetch(mid).await?;
+            Ok((channel_info(mid, &items), items))
+        },
+    )
+    .await
+}
+
+async fn fetch(mid: u64) -> Result<Vec<BiliData>, Rejection> {
+    let params = [
+        ("mid", mid.to_string()),
+        ("ps", "30".to_string()),
+        ("pn", "1".to_string()),
+        ("order", "pubdate".to_string()),
+    ];
+    let search = metrics::record_upstream(
+        SOURCE,
+        wbi::get::<Search>("https://api.bilibili.com/x/space/wbi/arc/search", &params),
+    )
+    .await
+    .map_err(reject::custom)?;
+    Ok(search.list.vlist.into_iter().map(BiliData::from).collect())
+}
+
+/// The uploader name is only known from the videos
+fn channel_info(mid: u64, items: &[BiliData]) -> ChannelInfo {
+    let name = items
+        .first()
+        .map_or_else(|| mid.to_string(), |d| d.owner.name.clone());
+    ChannelInfo {
+        title: format!("BiliBili uploader: {name}"),
+        link: format!("https://space.bilibili.com/{mid}/video"),
+        description: format!("Latest videos of BiliBili uploader {name}"),
+    }
+}
+
+#[test]
+fn map_submission() {
+    let s: Submission = serde_json::from_str(
+        r#"{"bvid": "BV1ph41177Mt", "typeid": 17, "pic": "http://i0.hdslb.com/a.jpg", "title": "t",
+            "author": "a", "mid": 1, "description": "d", "play": "--", "video_review": 3,
+            "created": 1676104200, "length": "1:02:03"}"#,
+    )
+    .unwrap();
+    let d = BiliData::from(s);
+    assert_eq!(d.tname, "单机游戏");
+    assert_eq!(d.stat.view, 0);
+    assert_eq!(d.duration, 3723);
+}
+
+#[test]
+fn unknown_type_without_category() {
+    let s: Submission = serde_json::from_str(
+        r#"{"bvid": "BV1ph41177Mt", "typeid": 9999, "pic": "http://i0.hdslb.com/a.jpg", "title": "t",
+            "author": "a", "mid": 1, "description": "d", "play": 1, "video_review": 3,
+            "created": 1676104200, "length": "01:02"}"#,
+    )
+    .unwrap();
+    let renderer = ItemRenderer::new(None, false, Default::default());
+    let item = crate::bilibili::rss_generator::create_item(&BiliData::from(s), &renderer);
+    assert!(item.categories().is_empty());
+}
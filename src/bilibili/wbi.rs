@@ -0,0 +1,140 @@
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use md5::{Digest, Md5};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use tracing::info;
+
+use crate::bilibili::{send, ApiResponse, CLIENT};
+use crate::error::MyError;
+use crate::status::unix_secs;
+
+/// Bilibili changes the keys without notice, refetch them hourly so a stale key is not used for long
+const KEY_TTL: Duration = Duration::from_secs(3600);
+
+/// Order to pick characters of img_key + sub_key to make the mixin key,
+/// see https://github.com/SocialSisterYi/bilibili-API-collect/blob/master/docs/misc/sign/wbi.md
+const MIXIN_KEY_ENC_TAB: [usize; 64] = [
+    46, 47, 18, 2, 53, 8, 23, 32, 15, 50, 10, 31, 58, 3, 45, 35, 27, 43, 5, 49, 33, 9, 42, 19, 29,
+    28, 14, 39, 12, 38, 41, 13, 37, 48, 7, 16, 24, 55, 40, 61, 26, 17, 0, 1, 60, 51, 30, 4, 22, 25,
+    54, 21, 56, 59, 6, 63, 57, 62, 11, 36, 20, 34, 44, 52,
+];
+
+/// Codes of a request rejected by the risk control, e.g. signed with a stale key
+const REJECTED_CODES: [i64; 2] = [-352, -403];
+
+static MIXIN_KEY: LazyLock<Mutex<Option<(Instant, String)>>> = LazyLock::new(|| Mutex::new(None));
+
+#[derive(Deserialize)]
+struct Nav {
+    wbi_img: WbiImg,
+}
+
+#[derive(Deserialize)]
+struct WbiImg {
+    img_url: String,
+    sub_url: String,
+}
+
+/// Call a WBI signed API and return its data
+pub async fn get<T: DeserializeOwned>(url: &str, params: &[(&str, String)]) -> Result<T, MyError> {
+    let mixin_key = mixin_key().await?;
+    let query = sign(params, &mixin_key, unix_secs(SystemTime::now()));
+    let response = send::<T>(&format!("{url}?{query}")).await?;
+    if REJECTED_CODES.contains(&response.code) {
+        info!(
+            "WBI signature rejected with {}, fetch new keys next time",
+            response.code
+        );
+        *MIXIN_KEY.lock().unwrap() = None;
+    }
+    response.into_data()
+}
+
+async fn mixin_key() -> Result<String, MyError> {
+    if let Some((fetched, key)) = MIXIN_KEY.lock().unwrap().as_ref() {
+        if fetched.elapsed() < KEY_TTL {
+            return Ok(key.clone());
+        }
+    }
+
+    info!("fetch WBI keys");
+    // not logged in, the code is -101 but the keys are still returned
+    let nav = CLIENT
+        .get("https://api.bilibili.com/x/web-interface/nav")
+        .send()
+        .await?
+        .json::<ApiResponse<Nav>>()
+        .await?;
+    let img = nav.data.ok_or(MyError::Api {
+        code: nav.code,
+        message: nav.message,
+    })?;
+    let key = mix(&key_of(&img.wbi_img.img_url), &key_of(&img.wbi_img.sub_url));
+    *MIXIN_KEY.lock().unwrap() = Some((Instant::now(), key.clone()));
+    Ok(key)
+}
+
+/// The file name of https://i0.hdslb.com/bfs/wbi/<key>.png
+fn key_of(url: &str) -> String {
+    let name = url.rsplit('/').next().unwrap_or_default();
+    name.split('.').next().unwrap_or_default().to_string()
+}
+
+fn mix(img_key: &str, sub_key: &str) -> String {
+    let raw = format!("{img_key}{sub_key}").into_bytes();
+    MIXIN_KEY_ENC_TAB
+        .iter()
+        .filter_map(|&i| raw.get(i).map(|&b| b as char))
+        .take(32)
+        .collect()
+}
+
+/// Sorted query with wts and its signature w_rid
+fn sign(params: &[(&str, String)], mixin_key: &str, wts: u64) -> String {
+    let mut params: Vec<(&str, String)> = params
+        .iter()
+        .map(|(k, v)| (*k, v.chars().filter(|c| !"!'()*".contains(*c)).collect()))
+        .chain([("wts", wts.to_string())])
+        .collect();
+    params.sort_by_key(|(k, _)| *k);
+    let query = params
+        .iter()
+        .map(|(k, v)| format!("{}={}", encode(k), encode(v)))
+        .collect::<Vec<_>>()
+        .join("&");
+    let w_rid = hex::encode(Md5::digest(format!("{query}{mixin_key}")));
+    format!("{query}&w_rid={w_rid}")
+}
+
+/// Like encodeURIComponent
+pub fn encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+#[test]
+fn sign_params() {
+    let mixin_key = mix(
+        &key_of("https://i0.hdslb.com/bfs/wbi/7cd084941338484aae1ad9425b84077c.png"),
+        &key_of("https://i0.hdslb.com/bfs/wbi/4932caff0ff746eab6f01bf08b70ac45.png"),
+    );
+    assert_eq!(mixin_key, "ea1db124af3c7062474693fa704f4ff8");
+
+    let params = [
+        ("foo", "114".to_string()),
+        ("bar", "514".to_string()),
+        ("zab", "1919810".to_string()),
+    ];
+    assert_eq!(
+        sign(&params, &mixin_key, 1702204169),
+        "bar=514&foo=114&wts=1702204169&zab=1919810&w_rid=8f6f2b5b3d485fe1886cec6a0be8c5d4"
+    );
+}
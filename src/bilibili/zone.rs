@@ -0,0 +1,177 @@
+use serde::Serialize;
+
+/// A Bilibili zone (分区), `parent` is 0 for top level zones
+#[derive(Debug, Serialize)]
+pub struct Zone {
+    pub tid: u32,
+    pub name: &'static str,
+    pub parent: u32,
+}
+
+const fn zone(tid: u32, name: &'static str, parent: u32) -> Zone {
+    Zone { tid, name, parent }
+}
+
+/// tid to parent zone mapping, see https://github.com/SocialSisterYi/bilibili-API-collect/blob/master/docs/video/video_zone.md
+pub const ZONES: &[Zone] = &[
+    zone(1, "动画", 0),
+    zone(24, "MAD·AMV", 1),
+    zone(25, "MMD·3D", 1),
+    zone(47, "短片·手书·配音", 1),
+    zone(210, "手办·模玩", 1),
+    zone(86, "特摄", 1),
+    zone(253, "动漫杂谈", 1),
+    zone(27, "综合", 1),
+    zone(13, "番剧", 0),
+    zone(51, "资讯", 13),
+    zone(152, "官方延伸", 13),
+    zone(32, "完结动画", 13),
+    zone(33, "连载动画", 13),
+    zone(167, "国创", 0),
+    zone(153, "国产动画", 167),
+    zone(168, "国产原创相关", 167),
+    zone(169, "布袋戏", 167),
+    zone(170, "资讯", 167),
+    zone(195, "动态漫·广播剧", 167),
+    zone(3, "音乐", 0),
+    zone(28, "原创音乐", 3),
+    zone(31, "翻唱", 3),
+    zone(30, "VOCALOID·UTAU", 3),
+    zone(59, "演奏", 3),
+    zone(193, "MV", 3),
+    zone(29, "音乐现场", 3),
+    zone(130, "音乐综合", 3),
+    zone(243, "乐评盘点", 3),
+    zone(244, "音乐教学", 3),
+    zone(194, "电音", 3),
+    zone(129, "舞蹈", 0),
+    zone(20, "宅舞", 129),
+    zone(198, "街舞", 129),
+    zone(199, "明星舞蹈", 129),
+    zone(200, "国风舞蹈", 129),
+    zone(255, "手势·网红舞", 129),
+    zone(154, "舞蹈综合", 129),
+    zone(156, "舞蹈教程", 129),
+    zone(4, "游戏", 0),
+    zone(17, "单机游戏", 4),
+    zone(171, "电子竞技", 4),
+    zone(172, "手机游戏", 4),
+    zone(65, "网络游戏", 4),
+    zone(173, "桌游棋牌", 4),
+    zone(121, "GMV", 4),
+    zone(136, "音游", 4),
+    zone(19, "Mugen", 4),
+    zone(36, "知识", 0),
+    zone(201, "科学科普", 36),
+    zone(124, "社科·法律·心理", 36),
+    zone(228, "人文历史", 36),
+    zone(207, "财经商业", 36),
+    zone(208, "校园学习", 36),
+    zone(209, "职业职场", 36),
+    zone(229, "设计·创意", 36),
+    zone(122, "野生技能协会", 36),
+    zone(188, "科技", 0),
+    zone(95, "数码", 188),
+    zone(230, "软件应用", 188),
+    zone(231, "计算机技术", 188),
+    zone(232, "科工机械", 188),
+    zone(233, "极客DIY", 188),
+    zone(234, "运动", 0),
+    zone(235, "篮球", 234),
+    zone(249, "足球", 234),
+    zone(164, "健身", 234),
+    zone(236, "竞技体育", 234),
+    zone(237, "运动文化", 234),
+    zone(238, "运动综合", 234),
+    zone(223, "汽车", 0),
+    zone(245, "赛车", 223),
+    zone(246, "改装玩车", 223),
+    zone(247, "新能源车", 223),
+    zone(248, "房车", 223),
+    zone(240, "摩托车", 223),
+    zone(227, "购车攻略", 223),
+    zone(176, "汽车生活", 223),
+    zone(160, "生活", 0),
+    zone(138, "搞笑", 160),
+    zone(250, "出行", 160),
+    zone(251, "三农", 160),
+    zone(239, "家居房产", 160),
+    zone(161, "手工", 160),
+    zone(162, "绘画", 160),
+    zone(21, "日常", 160),
+    zone(254, "亲子", 160),
+    zone(211, "美食", 0),
+    zone(76, "美食制作", 211),
+    zone(212, "美食侦探", 211),
+    zone(213, "美食测评", 211),
+    zone(214, "田园美食", 211),
+    zone(215, "美食记录", 211),
+    zone(217, "动物圈", 0),
+    zone(218, "喵星人", 217),
+    zone(219, "汪星人", 217),
+    zone(220, "大熊猫", 217),
+    zone(221, "野生动物", 217),
+    zone(222, "爬宠", 217),
+    zone(75, "动物综合", 217),
+    zone(119, "鬼畜", 0),
+    zone(22, "鬼畜调教", 119),
+    zone(26, "音MAD", 119),
+    zone(126, "人力VOCALOID", 119),
+    zone(216, "鬼畜剧场", 119),
+    zone(127, "教程演示", 119),
+    zone(155, "时尚", 0),
+    zone(157, "美妆护肤", 155),
+    zone(252, "仿妆cos", 155),
+    zone(158, "穿搭", 155),
+    zone(159, "时尚潮流", 155),
+    zone(202, "资讯", 0),
+    zone(203, "热点", 202),
+    zone(204, "环球", 202),
+    zone(205, "社会", 202),
+    zone(206, "综合", 202),
+    zone(5, "娱乐", 0),
+    zone(71, "综艺", 5),
+    zone(241, "娱乐杂谈", 5),
+    zone(242, "粉丝创作", 5),
+    zone(137, "明星综合", 5),
+    zone(181, "影视", 0),
+    zone(182, "影视杂谈", 181),
+    zone(183, "影视剪辑", 181),
+    zone(85, "小剧场", 181),
+    zone(184, "预告·资讯", 181),
+    zone(177, "纪录片", 0),
+    zone(37, "人文·历史", 177),
+    zone(178, "科学·探索·自然", 177),
+    zone(179, "军事", 177),
+    zone(180, "社会·美食·旅行", 177),
+    zone(23, "电影", 0),
+    zone(147, "华语电影", 23),
+    zone(145, "欧美电影", 23),
+    zone(146, "日本电影", 23),
+    zone(83, "其他国家", 23),
+    zone(11, "电视剧", 0),
+    zone(185, "国产剧", 11),
+    zone(187, "海外剧", 11),
+];
+
+pub fn find(tid: u32) -> Option<&'static Zone> {
+    ZONES.iter().find(|z| z.tid == tid)
+}
+
+/// Return the top level zone of a tid, a top level zone's parent is itself
+pub fn parent(tid: u32) -> Option<&'static Zone> {
+    find(tid).and_then(|z| {
+        if z.parent == 0 {
+            Some(z)
+        } else {
+            find(z.parent)
+        }
+    })
+}
+
+#[test]
+fn find_parent() {
+    assert_eq!(parent(17).unwrap().name, "游戏");
+    assert_eq!(parent(4).unwrap().name, "游戏");
+    assert!(parent(100000).is_none());
+}
@@ -1,41 +1,180 @@
 use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime};
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use sha2::{Digest, Sha256};
 use tokio::time::Instant;
+use tracing::info;
+
+use crate::metrics;
 
 pub struct RssCache {
-    cache: HashMap<CacheType, Content>
+    cache: HashMap<CacheKey, Content>,
+    /// Least recently used content is evicted when full
+    capacity: usize,
+    /// Incremented on every lookup, to order contents by last use
+    clock: AtomicU64,
 }
 
 pub struct Content {
     rss: String,
     record_instant: Instant,
+    /// For Last-Modified, Instant can't be converted to a date
+    record_time: SystemTime,
+    etag: String,
+    /// Compressed once here instead of on every request
+    gzip: Vec<u8>,
+    brotli: Vec<u8>,
+    /// clock value of the last lookup, atomic because lookups only hold the read lock
+    last_used: AtomicU64,
+}
+
+/// Identify a cached feed by where it comes from, its parameters and its format
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct CacheKey {
+    /// Source name, also used as metrics label, e.g. bilibili_ranking
+    pub source: &'static str,
+    /// Normalized query of the feed, e.g. rid=4&type=all, empty when the feed has no parameters
+    pub params: String,
+    pub format: Format,
+}
+
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash)]
+pub enum Format {
+    #[default]
+    Rss,
+}
+
+impl CacheKey {
+    /// Key of a feed without parameters
+    pub fn new(source: &'static str) -> Self {
+        CacheKey {
+            source,
+            params: String::new(),
+            format: Format::default(),
+        }
+    }
+
+    pub fn with_params(source: &'static str, params: impl Into<String>) -> Self {
+        CacheKey {
+            params: params.into(),
+            ..CacheKey::new(source)
+        }
+    }
+
+    /// Filtered by the bilibili blacklist
+    pub fn is_bilibili(&self) -> bool {
+        self.source.starts_with("bilibili")
+    }
 }
 
-#[derive(Debug, Eq, PartialEq, Hash)]
-pub enum CacheType {
-    Bilibili, Ddys
+impl Display for CacheKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.source)?;
+        if !self.params.is_empty() {
+            write!(f, "?{}", self.params)?;
+        }
+        Ok(())
+    }
 }
 
 impl RssCache {
-    pub fn new() -> Self {
-        RssCache{
-            cache: HashMap::new()
+    pub fn new(capacity: usize) -> Self {
+        RssCache {
+            cache: HashMap::new(),
+            capacity: capacity.max(1),
+            clock: AtomicU64::new(0),
         }
     }
 
-    pub fn get(&self, cache_type: &CacheType) -> Option<&Content> {
-        self.cache.get(cache_type)
+    pub fn get(&self, key: &CacheKey) -> Option<&Content> {
+        let content = self.cache.get(key)?;
+        let now = self.clock.fetch_add(1, Ordering::Relaxed) + 1;
+        content.last_used.store(now, Ordering::Relaxed);
+        Some(content)
     }
 
-    pub fn insert(&mut self, cache_type: CacheType, rss:String) {
-        self.cache.insert(cache_type, Content::new(rss));
+    /// Return the content if it is not expired, and record the lookup result in metrics
+    pub fn get_valid(&self, key: &CacheKey) -> Option<&Content> {
+        let (result, content) = match self.get(key) {
+            None => ("miss", None),
+            Some(c) if c.is_expired() => ("expired", None),
+            Some(c) => ("hit", Some(c)),
+        };
+        metrics::record_cache_lookup(key.source, result);
+        content
+    }
+
+    /// Insert or replace the content, evict the least recently used one when full
+    pub fn insert(&mut self, key: CacheKey, rss: String) -> &Content {
+        if !self.cache.contains_key(&key) && self.cache.len() >= self.capacity {
+            let lru = self
+                .cache
+                .iter()
+                .min_by_key(|(_, c)| c.last_used.load(Ordering::Relaxed))
+                .map(|(k, _)| k.clone());
+            if let Some(lru) = lru {
+                info!("cache is full, evict {lru}");
+                self.cache.remove(&lru);
+                metrics::record_cache_eviction(lru.source);
+            }
+        }
+
+        let content = Content::new(rss);
+        content.last_used.store(
+            self.clock.fetch_add(1, Ordering::Relaxed) + 1,
+            Ordering::Relaxed,
+        );
+        self.cache.entry(key).insert_entry(content).into_mut()
+    }
+
+    /// Age of every cached content
+    pub fn ages(&self) -> impl Iterator<Item = (&CacheKey, Duration)> {
+        self.cache
+            .iter()
+            .map(|(k, c)| (k, c.record_instant.elapsed()))
+    }
+
+    /// Remove every cache of the given keys
+    pub fn remove_matching(&mut self, f: impl Fn(&CacheKey) -> bool) {
+        self.cache.retain(|k, _| !f(k));
     }
 }
 
 impl Content {
     pub fn new(rss: String) -> Self {
+        let etag = format!(
+            "W/\"{}\"",
+            hex::encode(&Sha256::digest(rss.as_bytes())[..16])
+        );
+
+        let mut gzip = GzEncoder::new(Vec::new(), Compression::default());
+        gzip.write_all(rss.as_bytes()).unwrap();
+        let gzip = gzip.finish().unwrap();
+
+        let mut brotli = Vec::new();
+        brotli::BrotliCompress(
+            &mut rss.as_bytes(),
+            &mut brotli,
+            &brotli::enc::BrotliEncoderParams {
+                quality: 5,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
         Self {
             rss,
-            record_instant: Instant::now()
+            record_instant: Instant::now(),
+            record_time: SystemTime::now(),
+            etag,
+            gzip,
+            brotli,
+            last_used: AtomicU64::new(0),
         }
     }
 
@@ -47,4 +186,40 @@ impl Content {
     pub fn get_rss(&self) -> String {
         self.rss.clone()
     }
+
+    pub fn get_gzip(&self) -> Vec<u8> {
+        self.gzip.clone()
+    }
+
+    pub fn get_brotli(&self) -> Vec<u8> {
+        self.brotli.clone()
+    }
+
+    /// Weak ETag, because the same etag is used for every content encoding
+    pub fn etag(&self) -> &str {
+        &self.etag
+    }
+
+    pub fn last_modified(&self) -> SystemTime {
+        self.record_time
+    }
+}
+
+#[test]
+fn evict_least_recently_used() {
+    let mut cache = RssCache::new(2);
+    let (a, b, c) = (
+        CacheKey::new("a"),
+        CacheKey::with_params("b", "x=1"),
+        CacheKey::with_params("b", "x=2"),
+    );
+    cache.insert(a.clone(), "a".to_string());
+    cache.insert(b.clone(), "b".to_string());
+    assert!(cache.get(&a).is_some());
+
+    cache.insert(c.clone(), "c".to_string());
+    assert!(cache.get(&a).is_some());
+    assert!(cache.get(&b).is_none());
+    assert!(cache.get(&c).is_some());
+    assert_eq!(c.to_string(), "b?x=2");
 }
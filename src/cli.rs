@@ -1,7 +1,14 @@
+use std::fs;
 use std::net::IpAddr;
 use std::path::PathBuf;
+use std::process;
 
 use clap::Parser;
+use serde::Deserialize;
+use tracing::{error, info};
+
+use crate::aggregate::AggregateSource;
+use crate::server::ListenAddr;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -17,4 +24,110 @@ pub struct Cli {
     /// If this option is not set, blacklist update APIs will not work for safety
     #[arg(short, long)]
     pub auth_password: Option<String>,
+    /// Secret to sign the "block" links in feed items, links are added only when both this and public_url are set
+    #[arg(long)]
+    pub block_link_secret: Option<String>,
+    /// Public base URL of this server used in block links, e.g. https://rss.example.com
+    #[arg(long)]
+    pub public_url: Option<String>,
+    /// Use title + short link as bilibili item guid like older versions,
+    /// otherwise the guid is the video URL built from bvid, which survives title edits
+    #[arg(long, default_value_t = false)]
+    pub legacy_guid: bool,
+    /// Handlebars template of bilibili item descriptions, see README for the available fields
+    #[arg(long, value_name = "FILE")]
+    pub bilibili_template: Option<PathBuf>,
+    /// Handlebars template of ddys item descriptions
+    #[arg(long, value_name = "FILE")]
+    pub ddys_template: Option<PathBuf>,
+    /// Live room ids to notify when they go live, e.g. 5440,21452505
+    #[arg(long, value_name = "ROOM_ID", value_delimiter = ',')]
+    pub live_rooms: Vec<u64>,
+    /// How often to check the live rooms
+    #[arg(long, value_name = "SECONDS", default_value_t = 120)]
+    pub live_poll_interval: u64,
+    /// File to keep the live room status across restarts, so a room is not announced twice
+    #[arg(long, value_name = "FILE")]
+    pub live_state_path: Option<PathBuf>,
+    /// Max number of cached feeds, the least recently used one is dropped when full
+    #[arg(long, value_name = "N", default_value_t = 256)]
+    pub cache_capacity: usize,
+    /// Follow every ddys post link to add the year, rating and latest episode,
+    /// a post page is fetched again only when its title changes
+    #[arg(long, default_value_t = false)]
+    pub ddys_details: bool,
+    /// Max ddys post pages fetched at the same time
+    #[arg(long, value_name = "N", default_value_t = 4)]
+    pub ddys_detail_concurrency: usize,
+    /// Sources merged into GET /feed, an item in several sources is kept in the first one
+    #[arg(
+        long,
+        value_name = "SOURCE",
+        value_delimiter = ',',
+        default_value = "bilibili,ddys"
+    )]
+    pub aggregate_sources: Vec<AggregateSource>,
+    /// /status/ready fails when every source has been failing longer than this
+    #[arg(long, value_name = "SECONDS", default_value_t = 1800)]
+    pub readiness_threshold: u64,
+    /// How long to wait for in-flight requests on shutdown
+    #[arg(long, value_name = "SECONDS", default_value_t = 10)]
+    pub shutdown_timeout: u64,
+    /// Serve HTTPS with this PEM certificate chain, reloaded on SIGHUP
+    #[arg(long, value_name = "FILE", requires = "tls_key")]
+    pub tls_cert: Option<PathBuf>,
+    /// PEM private key of tls_cert
+    #[arg(long, value_name = "FILE", requires = "tls_cert")]
+    pub tls_key: Option<PathBuf>,
+    /// Also listen on this port with plain HTTP, redirecting to HTTPS
+    #[arg(long, value_name = "PORT", requires = "tls_cert")]
+    pub http_redirect_port: Option<u16>,
+    /// Listen address, can be repeated, e.g. 127.0.0.1:3000, [::1]:3000 or unix:/run/rss.sock.
+    /// Overrides host and port. Ignored under systemd socket activation
+    #[arg(short, long, value_name = "ADDR")]
+    pub listen: Vec<ListenAddr>,
+    /// JSON config file, e.g. {"listen": ["127.0.0.1:3000", "unix:/run/rss.sock"]}
+    #[arg(short, long, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+}
+
+/// Options that can be set in the config file, CLI options take precedence
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct FileConfig {
+    listen: Vec<String>,
+}
+
+impl Cli {
+    /// Listen addresses from CLI, then config file, then host and port
+    pub fn listen_addrs(&self) -> Vec<ListenAddr> {
+        if !self.listen.is_empty() {
+            return self.listen.clone();
+        }
+
+        let config = self.config.as_ref().map(|p| {
+            info!("use config at: {}", p.to_str().unwrap());
+            fs::read_to_string(p)
+                .map_err(|e| e.to_string())
+                .and_then(|s| serde_json::from_str::<FileConfig>(&s).map_err(|e| e.to_string()))
+                .unwrap_or_else(|e| {
+                    error!("fail to read config file: {e}");
+                    process::exit(1);
+                })
+        });
+        let listen: Result<Vec<ListenAddr>, String> = config
+            .unwrap_or_default()
+            .listen
+            .iter()
+            .map(|a| a.parse())
+            .collect();
+        match listen {
+            Ok(l) if !l.is_empty() => l,
+            Ok(_) => vec![ListenAddr::Tcp((self.host, self.port).into())],
+            Err(e) => {
+                error!("invalid config file: {e}");
+                process::exit(1);
+            }
+        }
+    }
 }
@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use futures::{stream, StreamExt};
+use scraper::{Html, Selector};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::ddys::Ddys;
+use crate::error::MyError;
+use crate::metrics;
+
+const SOURCE: &str = "ddys_detail";
+
+/// Information only on the post page
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct Detail {
+    /// Episode names in page order, e.g. 第01集
+    pub episodes: Vec<String>,
+    /// Douban rating
+    pub rating: Option<f32>,
+    pub year: Option<u16>,
+    /// Largest episode number, e.g. 27
+    pub latest_episode: Option<u32>,
+}
+
+/// Details by post URL, only fetched again when the post title changes, e.g. a new episode is added
+pub struct Details {
+    /// url to the title when fetched and the detail
+    posts: Mutex<HashMap<String, (String, Detail)>>,
+    /// Max detail pages fetched at the same time
+    concurrency: usize,
+}
+
+impl Details {
+    pub fn new(concurrency: usize) -> Self {
+        Details {
+            posts: Mutex::new(HashMap::new()),
+            concurrency: concurrency.max(1),
+        }
+    }
+
+    /// Fill in the detail of every post, failed ones are left empty and retried next time
+    pub async fn enrich(&self, posts: &mut [Ddys]) {
+        let changed: Vec<(String, String)> = {
+            let known = self.posts.lock().unwrap();
+            posts
+                .iter()
+                .filter(|p| !p.url.is_empty())
+                .filter(|p| known.get(&p.url).is_none_or(|(title, _)| *title != p.title))
+                .map(|p| (p.url.clone(), p.title.clone()))
+                .collect()
+        };
+        if !changed.is_empty() {
+            info!("fetch {} ddys detail pages", changed.len());
+        }
+
+        let fetched: Vec<_> = stream::iter(changed)
+            .map(|(url, title)| async move {
+                let detail = fetch(&url).await;
+                (url, title, detail)
+            })
+            .buffer_unordered(self.concurrency)
+            .collect()
+            .await;
+
+        let mut known = self.posts.lock().unwrap();
+        for (url, title, detail) in fetched {
+            match detail {
+                Ok(d) => {
+                    known.insert(url, (title, d));
+                }
+                Err(e) => warn!("fail to get ddys detail of {url}: {e}"),
+            }
+        }
+        // posts off the homepage are not needed anymore
+        known.retain(|url, _| posts.iter().any(|p| p.url == *url));
+        for p in posts.iter_mut() {
+            p.detail = known.get(&p.url).map(|(_, d)| d.clone());
+        }
+    }
+}
+
+async fn fetch(url: &str) -> Result<Detail, MyError> {
+    let html =
+        metrics::record_upstream(SOURCE, async { reqwest::get(url).await?.text().await }).await?;
+    Ok(parse(&html))
+}
+
+#[derive(Deserialize)]
+struct Playlist {
+    tracks: Vec<Track>,
+}
+
+#[derive(Deserialize)]
+struct Track {
+    caption: String,
+}
+
+fn parse(html: &str) -> Detail {
+    let document = Html::parse_document(html);
+    let playlist_selector = Selector::parse("script.wp-playlist-script").unwrap();
+    let abstract_selector = Selector::parse("div.abstract").unwrap();
+    let title_selector = Selector::parse("h1.post-title").unwrap();
+
+    let episodes: Vec<String> = document
+        .select(&playlist_selector)
+        .filter_map(|s| serde_json::from_str::<Playlist>(&s.text().collect::<String>()).ok())
+        .flat_map(|p| p.tracks)
+        .map(|t| t.caption)
+        .collect();
+    // Douban info, one "label: value" per line
+    let info: String = document
+        .select(&abstract_selector)
+        .flat_map(|a| a.text())
+        .collect::<Vec<_>>()
+        .join("\n");
+    let title: String = document
+        .select(&title_selector)
+        .flat_map(|t| t.text())
+        .collect();
+
+    Detail {
+        latest_episode: episodes.iter().filter_map(|e| last_number(e)).max(),
+        episodes,
+        rating: field(&info, "豆瓣评分").and_then(|r| r.parse().ok()),
+        // the title is like 狂飙 (2023) (更新至27集)
+        year: field(&info, "上映日期")
+            .and_then(|d| d.get(..4)?.parse().ok())
+            .or_else(|| {
+                title
+                    .split(['(', ')'])
+                    .filter(|s| s.len() == 4)
+                    .find_map(|y| y.parse().ok())
+            }),
+    }
+}
+
+/// Value after "label:" up to the line end
+fn field<'a>(info: &'a str, label: &str) -> Option<&'a str> {
+    info.lines()
+        .filter_map(|l| l.trim().strip_prefix(label))
+        .map(|v| v.trim_start_matches([':', '：', ' ']).trim())
+        .find(|v| !v.is_empty())
+}
+
+/// e.g. 27 of 第27集
+fn last_number(text: &str) -> Option<u32> {
+    text.split(|c: char| !c.is_ascii_digit())
+        .rfind(|s| !s.is_empty())?
+        .parse()
+        .ok()
+}
+
+#[test]
+fn parse_detail() {
+    let detail = parse(
+        r#"<html><body><h1 class="post-title">狂飙 (2023) (更新至2集)</h1>
+        <div class="abstract">导演: 徐纪周<br>上映日期: 2023-01-14(中国大陆)<br>豆瓣评分: 8.5<br></div>
+        <script type="application/json" class="wp-playlist-script">
+        {"type":"video","tracks":[{"src0":"/v/1.mp4","caption":"第01集"},{"src0":"/v/2.mp4","caption":"第02集"}]}
+        </script></body></html>"#,
+    );
+    assert_eq!(detail.episodes, ["第01集", "第02集"]);
+    assert_eq!(detail.latest_episode, Some(2));
+    assert_eq!(detail.rating, Some(8.5));
+    assert_eq!(detail.year, Some(2023));
+
+    let detail = parse(r#"<h1 class="post-title">阿凡达：水之道 (2022)</h1>"#);
+    assert_eq!(detail.year, Some(2022));
+    assert_eq!(detail.latest_episode, None);
+}
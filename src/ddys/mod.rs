@@ -1,11 +1,17 @@
+pub mod detail;
 pub mod rss_generator;
 
-#[derive(Default, Debug)]
+use serde::Serialize;
+
+use crate::ddys::detail::Detail;
+
+#[derive(Default, Debug, Serialize)]
 pub struct Ddys {
     pub title: String,
     pub category: Vec<String>,
     pub url: String,
     pub desc: String,
     pub image_url: String,
+    /// Only set when the ddys_details option is on
+    pub detail: Option<Detail>,
 }
-
@@ -3,45 +3,66 @@ use std::sync::Arc;
 use rss::validation::Validate;
 use rss::{ChannelBuilder, GuidBuilder, ImageBuilder, Item, ItemBuilder};
 use scraper::{Html, Selector};
+use serde::Serialize;
 use tokio::sync::RwLock;
 use tracing::info;
 use warp::{Rejection, Reply};
 
-use crate::cache::{CacheType, RssCache};
+use crate::cache::{CacheKey, RssCache};
+use crate::ddys::detail::{Detail, Details};
 use crate::ddys::Ddys;
 use crate::error::MyError;
-
-pub async fn generate_rss(cache: Arc<RwLock<RssCache>>) -> Result<impl Reply, Rejection> {
-    if let Some(content) = cache.read().await.get(&CacheType::Ddys) {
-        if !content.is_expired() {
-            info!("Cache is not expired, return cache content");
-            return Ok(reply(content.get_rss()));
-        }
+use crate::feed::{self, Conditions};
+use crate::html;
+use crate::metrics;
+use crate::template::Templates;
+
+/// Name of the cache and the description template
+pub const SOURCE: &str = "ddys";
+
+pub async fn generate_rss(
+    cache: Arc<RwLock<RssCache>>,
+    templates: Arc<Templates>,
+    details: Option<Arc<Details>>,
+    conditions: Conditions,
+) -> Result<impl Reply, Rejection> {
+    if let Some(content) = cache.read().await.get_valid(&CacheKey::new(SOURCE)) {
+        info!("Cache is not expired, return cache content");
+        return Ok(feed::reply(content, &conditions));
     }
 
     info!("Cache is None or expired, call API to generate rss");
-    match generate_new_rss().await {
+    match generate_new_rss(&templates, details.as_deref()).await {
         Ok(rss) => {
-            cache.write().await.insert(CacheType::Ddys, rss.clone());
-            Ok(reply(rss))
+            let mut cache = cache.write().await;
+            let content = cache.insert(CacheKey::new(SOURCE), rss);
+            Ok(feed::reply(content, &conditions))
         }
         Err(e) => Err(e),
     }
 }
 
-fn reply(rss: String) -> impl Reply {
-    warp::reply::with_header(rss, "content-type", "text/xml; charset=utf-8")
+async fn generate_new_rss(
+    templates: &Templates,
+    details: Option<&Details>,
+) -> Result<String, Rejection> {
+    let html = metrics::record_upstream(SOURCE, async {
+        reqwest::get("https://ddys.pro").await?.text().await
+    })
+    .await
+    .map_err(MyError::Reqwest)?;
+
+    let mut result = parse(&html);
+    if let Some(details) = details {
+        details.enrich(&mut result).await;
+    }
+    metrics::record_items(SOURCE, result.len(), result.len());
+    assemble(result, templates)
 }
 
-async fn generate_new_rss() -> Result<String, Rejection> {
-    let html = reqwest::get("https://ddys.pro")
-        .await
-        .map_err(MyError::Reqwest)?
-        .text()
-        .await
-        .map_err(MyError::Reqwest)?;
-
-    let fragment = Html::parse_document(&html);
+/// Posts on the homepage, Html is not Send so parse it before any await
+fn parse(html: &str) -> Vec<Ddys> {
+    let fragment = Html::parse_document(html);
     let post_selector = Selector::parse(r#" body > div[id="container"] > main > div[class="post-box-list"] > article > div[class="post-box-container"] "#).unwrap();
     let text_selector = Selector::parse(r#" div[class="post-box-text"] "#).unwrap();
     let category_selector = Selector::parse(r#" span[class="post-box-meta"] > a "#).unwrap();
@@ -93,7 +114,7 @@ async fn generate_new_rss() -> Result<String, Rejection> {
 
         result.push(ddys);
     }
-    assemble(result)
+    result
 }
 
 const TITLE: &str = "ddys.site";
@@ -101,7 +122,7 @@ const LINK: &str = "https://ddys.site";
 const DESC: &str = "A rss for ddys";
 const ICON_URL: &str = "https://ddys.art/favicon-32x32.png";
 
-fn assemble(items: Vec<Ddys>) -> Result<String, Rejection> {
+fn assemble(items: Vec<Ddys>, templates: &Templates) -> Result<String, Rejection> {
     let channel = ChannelBuilder::default()
         .title(TITLE)
         .link(LINK)
@@ -119,7 +140,7 @@ fn assemble(items: Vec<Ddys>) -> Result<String, Rejection> {
                 .map(|d| {
                     ItemBuilder::default()
                         .title(d.title.clone())
-                        .description(create_item_desc(d))
+                        .description(create_item_desc(d, templates))
                         .link(d.url.clone())
                         .guid(
                             // guid = title + url, because when an episode is updated, the page title is changed while url is not,
@@ -140,16 +161,56 @@ fn assemble(items: Vec<Ddys>) -> Result<String, Rejection> {
     Ok(channel.to_string())
 }
 
-fn create_item_desc(d: &Ddys) -> String {
+/// Fields available to the description template besides the ones of Ddys
+#[derive(Serialize)]
+struct DescContext<'a> {
+    #[serde(flatten)]
+    item: &'a Ddys,
+    /// desc escaped, with line breaks and links, use it with {{{desc_html}}}
+    desc_html: String,
+}
+
+fn create_item_desc(d: &Ddys, templates: &Templates) -> String {
+    let context = DescContext {
+        item: d,
+        desc_html: html::format_text(&d.desc),
+    };
+    if let Some(desc) = templates.render(SOURCE, &context) {
+        return desc;
+    }
+
     format!(
         r#"
     <b>category:</b> {category}
-    <p></p>
+    <p></p>{detail}
     <b>desc:</b> {desc}
     <p></p>
     <img style="width:100%" src="{img_src}" width="500">"#,
-        category = d.category.join(" "),
-        desc = d.desc,
-        img_src = d.image_url
+        category = html::escape(&d.category.join(" ")),
+        detail = d
+            .detail
+            .as_ref()
+            .map(create_detail_desc)
+            .unwrap_or_default(),
+        desc = context.desc_html,
+        img_src = html::escape(&d.image_url)
     )
 }
+
+/// Only the known fields, e.g. the year and rating of a movie
+fn create_detail_desc(detail: &Detail) -> String {
+    let mut fields = Vec::new();
+    if let Some(year) = detail.year {
+        fields.push(format!("<b>year:</b> {year}"));
+    }
+    if let Some(rating) = detail.rating {
+        fields.push(format!("<b>rating:</b> {rating}"));
+    }
+    if let Some(latest) = detail.latest_episode {
+        fields.push(format!("<b>latest episode:</b> {latest}"));
+    }
+    if fields.is_empty() {
+        return String::new();
+    }
+    format!("\n    {}\n    <p></p>", fields.join(" "))
+}
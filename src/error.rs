@@ -1,8 +1,10 @@
+use std::fmt::{Display, Formatter};
+
 use reqwest::StatusCode;
 use rss::validation::ValidationError;
 use tracing::info;
 use warp::body::BodyDeserializeError;
-use warp::reject::MissingHeader;
+use warp::reject::{InvalidQuery, MissingHeader};
 use warp::{reject, Rejection, Reply};
 
 /// Convert different crates' Error to MyError, used as warp's Rejection
@@ -12,10 +14,37 @@ pub enum MyError {
     Reqwest(reqwest::Error),
     AuthNotSet,
     UnAuthorized,
+    BlockLinkNotSet,
+    InvalidBlockLink,
+    BlockLinkExpired,
+    /// Bilibili API returned a non-zero code, e.g. -352 when the request is rejected by risk control
+    Api {
+        code: i64,
+        message: String,
+    },
+    /// A source feed can't be read back when merging the aggregate feed
+    Aggregate(String),
 }
 
 impl reject::Reject for MyError {}
 
+impl From<reqwest::Error> for MyError {
+    fn from(e: reqwest::Error) -> Self {
+        MyError::Reqwest(e)
+    }
+}
+
+/// Used in upstream fetch metrics and status
+impl Display for MyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MyError::Reqwest(e) => write!(f, "{e}"),
+            MyError::Api { code, message } => write!(f, "API error {code}: {message}"),
+            e => write!(f, "{e:?}"),
+        }
+    }
+}
+
 pub async fn return_error(r: Rejection) -> Result<impl Reply, Rejection> {
     info!("{:?}", r);
 
@@ -34,6 +63,46 @@ pub async fn return_error(r: Rejection) -> Result<impl Reply, Rejection> {
             "Authorization header is not equal to CLI option auth_password".to_string(),
             StatusCode::UNAUTHORIZED,
         ))
+    } else if let Some(MyError::BlockLinkNotSet) = r.find::<MyError>() {
+        Ok(warp::reply::with_status(
+            "Block link is disabled. To enable it, set the CLI options block_link_secret and public_url".to_string(),
+            StatusCode::FORBIDDEN,
+        ))
+    } else if let Some(MyError::InvalidBlockLink) = r.find::<MyError>() {
+        Ok(warp::reply::with_status(
+            "Block link signature is invalid".to_string(),
+            StatusCode::FORBIDDEN,
+        ))
+    } else if let Some(MyError::BlockLinkExpired) = r.find::<MyError>() {
+        Ok(warp::reply::with_status(
+            "Block link is expired".to_string(),
+            StatusCode::GONE,
+        ))
+    } else if let Some(MyError::Reqwest(e)) = r.find::<MyError>() {
+        Ok(warp::reply::with_status(
+            format!("Fail to fetch upstream content: {e}"),
+            StatusCode::BAD_GATEWAY,
+        ))
+    } else if let Some(e @ MyError::Api { .. }) = r.find::<MyError>() {
+        Ok(warp::reply::with_status(
+            format!("Fail to fetch upstream content: {e}"),
+            StatusCode::BAD_GATEWAY,
+        ))
+    } else if let Some(MyError::Validation(e)) = r.find::<MyError>() {
+        Ok(warp::reply::with_status(
+            format!("Generated rss is invalid: {e}"),
+            StatusCode::INTERNAL_SERVER_ERROR,
+        ))
+    } else if let Some(MyError::Aggregate(e)) = r.find::<MyError>() {
+        Ok(warp::reply::with_status(
+            format!("Fail to merge feeds: {e}"),
+            StatusCode::INTERNAL_SERVER_ERROR,
+        ))
+    } else if let Some(e) = r.find::<InvalidQuery>() {
+        Ok(warp::reply::with_status(
+            e.to_string(),
+            StatusCode::BAD_REQUEST,
+        ))
     } else if let Some(m) = r.find::<MissingHeader>() {
         Ok(warp::reply::with_status(
             m.to_string(),
@@ -0,0 +1,125 @@
+use std::time::SystemTime;
+
+use warp::http::{header, Response, StatusCode};
+use warp::hyper::Body;
+use warp::reply::Response as WarpResponse;
+use warp::Filter;
+
+use crate::cache::Content;
+use crate::status::unix_secs;
+
+/// Request headers for conditional GET and content negotiation
+#[derive(Debug, Default)]
+pub struct Conditions {
+    if_none_match: Option<String>,
+    if_modified_since: Option<SystemTime>,
+    accept_encoding: Option<String>,
+}
+
+pub fn conditions() -> impl Filter<Extract = (Conditions,), Error = std::convert::Infallible> + Clone
+{
+    warp::header::optional::<String>("if-none-match")
+        .and(warp::header::optional::<String>("if-modified-since"))
+        .and(warp::header::optional::<String>("accept-encoding"))
+        .map(
+            |if_none_match, if_modified_since: Option<String>, accept_encoding| Conditions {
+                if_none_match,
+                if_modified_since: if_modified_since
+                    .and_then(|s| httpdate::parse_http_date(&s).ok()),
+                accept_encoding,
+            },
+        )
+        .or(warp::any().map(Conditions::default))
+        .unify()
+}
+
+impl Conditions {
+    /// If-None-Match takes precedence over If-Modified-Since
+    fn not_modified(&self, content: &Content) -> bool {
+        match (&self.if_none_match, self.if_modified_since) {
+            // weak comparison, ignore the W/ prefix
+            (Some(tags), _) => tags.split(',').map(str::trim).any(|t| {
+                t == "*" || t.trim_start_matches("W/") == content.etag().trim_start_matches("W/")
+            }),
+            // http date has second precision
+            (None, Some(since)) => unix_secs(content.last_modified()) <= unix_secs(since),
+            (None, None) => false,
+        }
+    }
+
+    /// Prefer brotli, then gzip
+    fn encoding(&self) -> Option<&'static str> {
+        let accepted: Vec<&str> = self
+            .accept_encoding
+            .as_deref()
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|e| {
+                let mut parts = e.split(';').map(str::trim);
+                let name = parts.next()?;
+                let rejected = parts.any(|p| p == "q=0" || p == "q=0.0" || p == "q=0.00");
+                (!rejected).then_some(name)
+            })
+            .collect();
+        ["br", "gzip"].into_iter().find(|e| accepted.contains(e))
+    }
+}
+
+/// Reply the rss content, with 304 when the reader already has it, compressed if accepted
+pub fn reply(content: &Content, conditions: &Conditions) -> WarpResponse {
+    let builder = Response::builder()
+        .header(header::ETAG, content.etag())
+        .header(
+            header::LAST_MODIFIED,
+            httpdate::fmt_http_date(content.last_modified()),
+        )
+        .header(header::VARY, "accept-encoding");
+
+    if conditions.not_modified(content) {
+        return builder
+            .status(StatusCode::NOT_MODIFIED)
+            .body(Body::empty())
+            .unwrap();
+    }
+
+    let builder = builder.header(header::CONTENT_TYPE, "text/xml; charset=utf-8");
+    match conditions.encoding() {
+        Some("br") => builder
+            .header(header::CONTENT_ENCODING, "br")
+            .body(Body::from(content.get_brotli())),
+        Some("gzip") => builder
+            .header(header::CONTENT_ENCODING, "gzip")
+            .body(Body::from(content.get_gzip())),
+        _ => builder.body(Body::from(content.get_rss())),
+    }
+    .unwrap()
+}
+
+#[test]
+fn conditional_reply() {
+    let content = Content::new("<rss></rss>".to_string());
+    let conditions = |if_none_match: Option<&str>, accept_encoding: Option<&str>| Conditions {
+        if_none_match: if_none_match.map(str::to_string),
+        if_modified_since: None,
+        accept_encoding: accept_encoding.map(str::to_string),
+    };
+
+    let r = reply(&content, &conditions(Some(content.etag()), None));
+    assert_eq!(r.status(), StatusCode::NOT_MODIFIED);
+
+    let r = reply(
+        &content,
+        &conditions(Some("\"other\""), Some("gzip, deflate")),
+    );
+    assert_eq!(r.status(), StatusCode::OK);
+    assert_eq!(r.headers()[header::CONTENT_ENCODING], "gzip");
+
+    let r = reply(&content, &conditions(None, Some("gzip, br;q=0")));
+    assert_eq!(r.headers()[header::CONTENT_ENCODING], "gzip");
+
+    let since = Conditions {
+        if_modified_since: Some(SystemTime::now()),
+        ..Default::default()
+    };
+    assert_eq!(reply(&content, &since).status(), StatusCode::NOT_MODIFIED);
+}
@@ -0,0 +1,124 @@
+/// Escape upstream text to put it in HTML content or a quoted attribute
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Turn HTML like search result titles with <em class="keyword"> highlighting back to plain text
+pub fn strip_tags(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            _ if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    unescape(&text)
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Escape plain text like a video description, keep its line breaks,
+/// and turn URLs and BV ids into links
+pub fn format_text(text: &str) -> String {
+    let mut formatted = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some((start, end, href)) = next_link(rest) {
+        formatted.push_str(&escape_lines(&rest[..start]));
+        formatted.push_str(&format!(
+            r#"<a href="{}">{}</a>"#,
+            escape(&href),
+            escape(&rest[start..end])
+        ));
+        rest = &rest[end..];
+    }
+    formatted.push_str(&escape_lines(rest));
+    formatted
+}
+
+fn escape_lines(text: &str) -> String {
+    escape(text).replace("\r\n", "\n").replace('\n', "<br>")
+}
+
+/// Position and target of the first link in text
+fn next_link(text: &str) -> Option<(usize, usize, String)> {
+    let url = ["https://", "http://"]
+        .iter()
+        .filter_map(|p| text.find(p))
+        .min()
+        .map(|start| {
+            let len = text[start..]
+                .find(|c: char| c.is_whitespace() || !c.is_ascii() || "<>\"'".contains(c))
+                .unwrap_or(text.len() - start);
+            let url =
+                text[start..start + len].trim_end_matches(['.', ',', ';', ':', '!', '?', ')']);
+            (start, start + url.len(), url.to_string())
+        });
+    let bvid = find_bvid(text).map(|start| {
+        let end = start + BVID_LEN;
+        (
+            start,
+            end,
+            format!("https://www.bilibili.com/video/{}", &text[start..end]),
+        )
+    });
+
+    match (url, bvid) {
+        (Some(u), Some(b)) => Some(if b.0 < u.0 { b } else { u }),
+        (u, b) => u.or(b),
+    }
+}
+
+/// "BV" and 10 letters or digits
+const BVID_LEN: usize = 12;
+
+fn find_bvid(text: &str) -> Option<usize> {
+    let bytes = text.as_bytes();
+    text.match_indices("BV").map(|(i, _)| i).find(|&i| {
+        let end = i + BVID_LEN;
+        (i == 0 || !bytes[i - 1].is_ascii_alphanumeric())
+            && end <= bytes.len()
+            && bytes[i + 2..end].iter().all(u8::is_ascii_alphanumeric)
+            && bytes.get(end).is_none_or(|b| !b.is_ascii_alphanumeric())
+    })
+}
+
+#[test]
+fn strip_highlight() {
+    assert_eq!(
+        strip_tags(r#"【<em class="keyword">原神</em>】A &amp; B &lt;3"#),
+        "【原神】A & B <3"
+    );
+}
+
+#[test]
+fn format_desc() {
+    assert_eq!(
+        format_text("<img onerror=x>\n第一期：BV1Uv4y1h7Gb，见 https://b23.tv/abc?a=1&b=2."),
+        "&lt;img onerror=x&gt;<br>第一期：<a href=\"https://www.bilibili.com/video/BV1Uv4y1h7Gb\">BV1Uv4y1h7Gb</a>，\
+         见 <a href=\"https://b23.tv/abc?a=1&amp;b=2\">https://b23.tv/abc?a=1&amp;b=2</a>."
+    );
+    assert_eq!(
+        format_text("https://www.bilibili.com/video/BV1Uv4y1h7Gb ABV1Uv4y1h7Gb"),
+        "<a href=\"https://www.bilibili.com/video/BV1Uv4y1h7Gb\">https://www.bilibili.com/video/BV1Uv4y1h7Gb</a> ABV1Uv4y1h7Gb"
+    );
+}
@@ -1,6 +1,7 @@
 extern crate core;
 
 use std::sync::Arc;
+use std::time::Duration;
 
 use clap::Parser;
 use futures::future;
@@ -11,16 +12,30 @@ use warp::{reject, Filter};
 
 use bilibili::blacklist;
 use bilibili::blacklist::Blacklist;
+use bilibili::block_link::BlockLinkSigner;
+use bilibili::live::LiveRooms;
+use bilibili::rss_generator::ItemRenderer;
 
+use crate::aggregate::Aggregator;
 use crate::cache::RssCache;
 use crate::cli::Cli;
+use crate::ddys::detail::Details;
 use crate::error::MyError;
+use crate::template::Templates;
 
+mod aggregate;
 mod bilibili;
 mod cache;
 mod cli;
 mod ddys;
 mod error;
+mod feed;
+mod html;
+mod metrics;
+mod server;
+mod shutdown;
+mod status;
+mod template;
 
 #[tokio::main]
 async fn main() {
@@ -31,7 +46,9 @@ async fn main() {
         .with_span_events(FmtSpan::CLOSE)
         .init();
 
+    status::init();
     let cli = Cli::parse();
+    let listen_addrs = cli.listen_addrs();
 
     if cli.auth_password.is_none() {
         info!("User didn't set auth_password, the update blacklist API will not work");
@@ -44,9 +61,65 @@ async fn main() {
         Arc::new(RwLock::new(Blacklist::from(cli.blacklist_path)))
     };
 
+    let blacklist_to_flush = Arc::clone(&blacklist);
     let blacklist_filter = warp::any().map(move || Arc::clone(&blacklist));
 
-    let cache = Arc::new(RwLock::new(RssCache::new()));
+    let signer = match (cli.block_link_secret, cli.public_url) {
+        (Some(secret), Some(url)) => Some(Arc::new(BlockLinkSigner::new(secret, url))),
+        _ => {
+            info!(
+                "User didn't set block_link_secret and public_url, block links will not be added"
+            );
+            None
+        }
+    };
+    let templates = Arc::new(Templates::load(&[
+        (bilibili::rss_generator::SOURCE, cli.bilibili_template),
+        (ddys::rss_generator::SOURCE, cli.ddys_template),
+    ]));
+
+    let renderer = Arc::new(ItemRenderer::new(
+        signer.clone(),
+        cli.legacy_guid,
+        Arc::clone(&templates),
+    ));
+
+    let ddys_details = cli
+        .ddys_details
+        .then(|| Arc::new(Details::new(cli.ddys_detail_concurrency)));
+
+    let cache = Arc::new(RwLock::new(RssCache::new(cli.cache_capacity)));
+
+    let live_rooms = Arc::new(RwLock::new(LiveRooms::new(
+        cli.live_rooms,
+        cli.live_state_path,
+    )));
+    let live_rooms_to_flush = Arc::clone(&live_rooms);
+    if !live_rooms.read().await.is_empty() {
+        tokio::spawn(bilibili::live::poll(
+            Arc::clone(&live_rooms),
+            Arc::clone(&cache),
+            Duration::from_secs(cli.live_poll_interval),
+        ));
+    }
+
+    let aggregator = Arc::new(Aggregator {
+        sources: cli.aggregate_sources,
+        blacklist: Arc::clone(&blacklist_to_flush),
+        cache: Arc::clone(&cache),
+        renderer: Arc::clone(&renderer),
+        templates: Arc::clone(&templates),
+        ddys_details: ddys_details.clone(),
+        live_rooms: Arc::clone(&live_rooms),
+    });
+    let aggregator_filter = warp::any().map(move || Arc::clone(&aggregator));
+
+    let renderer_filter = warp::any().map(move || Arc::clone(&renderer));
+    let templates_filter = warp::any().map(move || Arc::clone(&templates));
+    let signer_filter = warp::any().map(move || signer.clone());
+    let ddys_details_filter = warp::any().map(move || ddys_details.clone());
+    let live_rooms_filter = warp::any().map(move || Arc::clone(&live_rooms));
+
     let cache_filter = warp::any().map(move || Arc::clone(&cache));
 
     let check_update_api_filter = warp::any()
@@ -68,19 +141,130 @@ async fn main() {
         .and(warp::path!("bilibili" / "feed"))
         .and(blacklist_filter.clone())
         .and(cache_filter.clone())
+        .and(renderer_filter.clone())
+        .and(feed::conditions())
         .and_then(bilibili::rss_generator::generate_rss);
 
+    // GET /bilibili/ranking?rid=...&type=...
+    let get_ranking = warp::get()
+        .and(warp::path!("bilibili" / "ranking"))
+        .and(blacklist_filter.clone())
+        .and(cache_filter.clone())
+        .and(renderer_filter.clone())
+        .and(warp::query())
+        .and(feed::conditions())
+        .and_then(bilibili::ranking::generate_rss);
+
+    // GET /bilibili/popular
+    let get_popular = warp::get()
+        .and(warp::path!("bilibili" / "popular"))
+        .and(blacklist_filter.clone())
+        .and(cache_filter.clone())
+        .and(renderer_filter.clone())
+        .and(feed::conditions())
+        .and_then(bilibili::popular::generate_popular);
+
+    // GET /bilibili/weekly
+    let get_weekly = warp::get()
+        .and(warp::path!("bilibili" / "weekly"))
+        .and(blacklist_filter.clone())
+        .and(cache_filter.clone())
+        .and(renderer_filter.clone())
+        .and(feed::conditions())
+        .and_then(bilibili::popular::generate_weekly);
+
+    // GET /bilibili/user/{mid}/feed
+    let get_user = warp::get()
+        .and(warp::path!("bilibili" / "user" / u64 / "feed"))
+        .and(blacklist_filter.clone())
+        .and(cache_filter.clone())
+        .and(renderer_filter.clone())
+        .and(feed::conditions())
+        .and_then(bilibili::user::generate_rss);
+
+    // GET /bilibili/search?keyword=...&order=...
+    let get_search = warp::get()
+        .and(warp::path!("bilibili" / "search"))
+        .and(blacklist_filter.clone())
+        .and(cache_filter.clone())
+        .and(renderer_filter.clone())
+        .and(warp::query())
+        .and(feed::conditions())
+        .and_then(bilibili::search::generate_rss);
+
+    // GET /bilibili/live
+    let get_live_rooms = warp::get()
+        .and(warp::path!("bilibili" / "live"))
+        .and(live_rooms_filter)
+        .and(cache_filter.clone())
+        .and(feed::conditions())
+        .and_then(bilibili::live::generate_rss);
+
+    // GET /bilibili/favorites/{media_id}
+    let get_favorites = warp::get()
+        .and(warp::path!("bilibili" / "favorites" / u64))
+        .and(blacklist_filter.clone())
+        .and(cache_filter.clone())
+        .and(renderer_filter.clone())
+        .and(feed::conditions())
+        .and_then(bilibili::collection::generate_favorites);
+
+    // GET /bilibili/user/{mid}/season/{season_id}
+    let get_season = warp::get()
+        .and(warp::path!("bilibili" / "user" / u64 / "season" / u64))
+        .and(blacklist_filter.clone())
+        .and(cache_filter.clone())
+        .and(renderer_filter.clone())
+        .and(feed::conditions())
+        .and_then(bilibili::collection::generate_season);
+
+    // GET /bilibili/user/{mid}/series/{series_id}
+    let get_series = warp::get()
+        .and(warp::path!("bilibili" / "user" / u64 / "series" / u64))
+        .and(blacklist_filter.clone())
+        .and(cache_filter.clone())
+        .and(renderer_filter.clone())
+        .and(feed::conditions())
+        .and_then(bilibili::collection::generate_series);
+
+    // GET /bilibili/bangumi?type=...&seasons=...
+    let get_bangumi = warp::get()
+        .and(warp::path!("bilibili" / "bangumi"))
+        .and(cache_filter.clone())
+        .and(warp::query())
+        .and(feed::conditions())
+        .and_then(bilibili::bangumi::generate_rss);
+
+    // GET /bilibili/feed/debug
+    let get_rss_debug = warp::get()
+        .and(warp::path!("bilibili" / "feed" / "debug"))
+        .and(blacklist_filter.clone())
+        .and(cache_filter.clone())
+        .and(renderer_filter.clone())
+        .and_then(bilibili::rss_generator::generate_debug);
+
     // GET /bilibili/blacklist
     let get_blacklist = warp::get()
         .and(warp::path!("bilibili" / "blacklist"))
         .and(blacklist_filter.clone())
         .then(|b: Arc<RwLock<Blacklist>>| async move { warp::reply::json(&*b.read().await) });
 
+    // GET /bilibili/zones
+    let get_zones = warp::get()
+        .and(warp::path!("bilibili" / "zones"))
+        .map(|| warp::reply::json(&bilibili::zone::ZONES));
+
+    // GET /bilibili/blacklist/edit
+    let get_blacklist_page = warp::get()
+        .and(warp::path!("bilibili" / "blacklist" / "edit"))
+        .map(|| warp::reply::html(include_str!("../resources/var/www/blacklist.html")));
+
     // PATCH /bilibili/blacklist
     let patch_blacklist = warp::patch()
         .and(check_update_api_filter.clone())
         .and(warp::path!("bilibili" / "blacklist"))
         .and(blacklist_filter.clone())
+        .and(cache_filter.clone())
         .and(warp::body::content_length_limit(32 * 1024))
         .and(warp::body::json())
         .and_then(blacklist::patch_blacklist);
@@ -90,27 +274,128 @@ async fn main() {
         .and(check_update_api_filter.clone())
         .and(warp::path!("bilibili" / "blacklist"))
         .and(blacklist_filter.clone())
+        .and(cache_filter.clone())
         .and(warp::body::content_length_limit(32 * 1024))
         .and(warp::body::json())
         .and_then(blacklist::put_blacklist);
 
+    // GET /bilibili/block?mid=...&author=...&expires=...&sig=...
+    let get_block = warp::get()
+        .and(warp::path!("bilibili" / "block"))
+        .and(signer_filter.clone())
+        .and(warp::query())
+        .and_then(bilibili::block_link::confirm);
+
+    // POST /bilibili/block with the same parameters as a form
+    let post_block = warp::post()
+        .and(warp::path!("bilibili" / "block"))
+        .and(signer_filter.clone())
+        .and(blacklist_filter.clone())
+        .and(cache_filter.clone())
+        .and(warp::body::content_length_limit(4 * 1024))
+        .and(warp::body::form())
+        .and_then(bilibili::block_link::block);
+
+    // GET /
+    let get_index = warp::get()
+        .and(warp::path::end())
+        .map(|| warp::reply::html(include_str!("../resources/var/www/index.html")));
+
+    // GET /favicon.ico
+    let get_favicon = warp::get().and(warp::path!("favicon.ico")).map(|| {
+        warp::reply::with_header(
+            &include_bytes!("../resources/var/www/favicon.ico")[..],
+            "content-type",
+            "image/x-icon",
+        )
+    });
+
     // GET /status
-    let get_status = warp::get().and(warp::path!("status")).map(|| "ok");
+    let get_status = warp::get()
+        .and(warp::path!("status"))
+        .and(blacklist_filter.clone())
+        .and(cache_filter.clone())
+        .then(status::status);
+
+    // GET /status/live
+    let get_live = warp::get()
+        .and(warp::path!("status" / "live"))
+        .map(status::live);
+
+    // GET /status/ready
+    let readiness_threshold = Duration::from_secs(cli.readiness_threshold);
+    let get_ready = warp::get()
+        .and(warp::path!("status" / "ready"))
+        .map(move || status::ready(readiness_threshold));
+
+    // GET /metrics
+    let get_metrics = warp::get()
+        .and(warp::path!("metrics"))
+        .and(blacklist_filter.clone())
+        .then(metrics::export);
+
+    // GET /feed
+    let get_aggregate = warp::get()
+        .and(warp::path!("feed"))
+        .and(aggregator_filter)
+        .and(feed::conditions())
+        .and_then(aggregate::generate_rss);
 
     // GET /ddys/feed
     let get_ddys = warp::get()
         .and(warp::path!("ddys" / "feed"))
         .and(cache_filter.clone())
+        .and(templates_filter.clone())
+        .and(ddys_details_filter)
+        .and(feed::conditions())
         .and_then(ddys::rss_generator::generate_rss);
 
-    let routes = get_rss
+    let routes = get_index
+        .or(get_favicon)
+        .or(get_rss)
+        .or(get_rss_debug)
+        .or(get_ranking)
+        .or(get_popular)
+        .or(get_weekly)
+        .or(get_user)
+        .or(get_search)
+        .or(get_favorites)
+        .or(get_season)
+        .or(get_series)
+        .or(get_bangumi)
+        .or(get_live_rooms)
         .or(get_blacklist)
+        .or(get_blacklist_page)
+        .or(get_zones)
         .or(patch_blacklist)
         .or(put_blacklist)
+        .or(get_block)
+        .or(post_block)
         .or(get_status)
+        .or(get_live)
+        .or(get_ready)
         .or(get_ddys)
+        .or(get_aggregate)
+        .or(get_metrics)
         .with(warp::trace::request())
-        .recover(error::return_error);
+        .recover(error::return_error)
+        .with(warp::log::custom(metrics::record_request));
+
+    let listen = server::Listen {
+        addrs: listen_addrs,
+        tls: cli
+            .tls_cert
+            .zip(cli.tls_key)
+            .map(|(cert, key)| server::TlsFiles { cert, key }),
+        http_redirect_port: cli.http_redirect_port,
+    };
+    shutdown::run_until_signal(
+        |stop| server::serve(routes, listen, stop),
+        Duration::from_secs(cli.shutdown_timeout),
+    )
+    .await;
 
-    warp::serve(routes).run((cli.host, cli.port)).await;
+    blacklist_to_flush.write().await.flush();
+    live_rooms_to_flush.write().await.flush();
+    info!("shutdown complete");
 }
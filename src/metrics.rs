@@ -0,0 +1,201 @@
+use std::fmt::Display;
+use std::future::Future;
+use std::sync::{Arc, LazyLock};
+
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts, Registry, TextEncoder,
+};
+use tokio::sync::RwLock;
+use warp::log::Info;
+use warp::Reply;
+
+use crate::blacklist::Blacklist;
+use crate::status;
+
+static REGISTRY: LazyLock<Registry> = LazyLock::new(Registry::new);
+
+fn register<T: prometheus::core::Collector + Clone + 'static>(collector: T) -> T {
+    REGISTRY.register(Box::new(collector.clone())).unwrap();
+    collector
+}
+
+static HTTP_REQUESTS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register(
+        IntCounterVec::new(
+            Opts::new("http_requests_total", "HTTP requests by route"),
+            &["route", "method", "status"],
+        )
+        .unwrap(),
+    )
+});
+
+static HTTP_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register(
+        HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "HTTP request latency by route",
+            ),
+            &["route"],
+        )
+        .unwrap(),
+    )
+});
+
+static CACHE_LOOKUPS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register(
+        IntCounterVec::new(
+            Opts::new(
+                "cache_lookups_total",
+                "RSS cache lookups by cache type and result",
+            ),
+            &["cache_type", "result"],
+        )
+        .unwrap(),
+    )
+});
+
+static CACHE_EVICTIONS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register(
+        IntCounterVec::new(
+            Opts::new(
+                "cache_evictions_total",
+                "RSS cache evictions when it is full by source",
+            ),
+            &["source"],
+        )
+        .unwrap(),
+    )
+});
+
+static UPSTREAM_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register(
+        HistogramVec::new(
+            HistogramOpts::new(
+                "upstream_fetch_duration_seconds",
+                "Upstream fetch duration by source",
+            ),
+            &["source"],
+        )
+        .unwrap(),
+    )
+});
+
+static UPSTREAM_ERRORS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register(
+        IntCounterVec::new(
+            Opts::new(
+                "upstream_fetch_errors_total",
+                "Upstream fetch errors by source",
+            ),
+            &["source"],
+        )
+        .unwrap(),
+    )
+});
+
+static ITEMS_FETCHED: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register(
+        IntCounterVec::new(
+            Opts::new(
+                "items_fetched_total",
+                "Items fetched from upstream by source",
+            ),
+            &["source"],
+        )
+        .unwrap(),
+    )
+});
+
+static ITEMS_FILTERED: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register(
+        IntCounterVec::new(
+            Opts::new(
+                "items_filtered_total",
+                "Items filtered out by the blacklist by source",
+            ),
+            &["source"],
+        )
+        .unwrap(),
+    )
+});
+
+static BLACKLIST_SIZE: LazyLock<IntGauge> = LazyLock::new(|| {
+    register(IntGauge::new("blacklist_size", "Number of blacklist entries").unwrap())
+});
+
+/// Used with warp::log::custom, record every response.
+/// Unmatched paths share one label, and ids in paths are replaced,
+/// so random URLs can't blow up the metric cardinality
+pub fn record_request(info: Info) {
+    let route = if info.status() == warp::http::StatusCode::NOT_FOUND {
+        "unmatched".to_string()
+    } else {
+        route_of(info.path())
+    };
+    let route = route.as_str();
+    HTTP_REQUESTS
+        .with_label_values(&[route, info.method().as_str(), info.status().as_str()])
+        .inc();
+    HTTP_DURATION
+        .with_label_values(&[route])
+        .observe(info.elapsed().as_secs_f64());
+}
+
+/// Replace numeric path segments like /bilibili/user/123/feed to /bilibili/user/:id/feed
+fn route_of(path: &str) -> String {
+    path.split('/')
+        .map(|s| {
+            if !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit()) {
+                ":id"
+            } else {
+                s
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// result is one of hit, miss and expired
+pub fn record_cache_lookup(cache_type: &str, result: &str) {
+    CACHE_LOOKUPS.with_label_values(&[cache_type, result]).inc();
+}
+
+pub fn record_cache_eviction(source: &str) {
+    CACHE_EVICTIONS.with_label_values(&[source]).inc();
+}
+
+/// Time an upstream fetch, and count it as an error if it fails.
+/// The result is also recorded in the source's health status
+pub async fn record_upstream<T, E: Display>(
+    source: &str,
+    fetch: impl Future<Output = Result<T, E>>,
+) -> Result<T, E> {
+    let timer = UPSTREAM_DURATION.with_label_values(&[source]).start_timer();
+    let result = fetch.await;
+    timer.observe_duration();
+    if result.is_err() {
+        UPSTREAM_ERRORS.with_label_values(&[source]).inc();
+    }
+    status::record_fetch(source, result.as_ref().err().map(|e| e.to_string()));
+    result
+}
+
+pub fn record_items(source: &str, fetched: usize, kept: usize) {
+    ITEMS_FETCHED
+        .with_label_values(&[source])
+        .inc_by(fetched as u64);
+    ITEMS_FILTERED
+        .with_label_values(&[source])
+        .inc_by((fetched - kept) as u64);
+}
+
+/// GET /metrics, in Prometheus text format
+pub async fn export(blacklist: Arc<RwLock<Blacklist>>) -> impl Reply {
+    BLACKLIST_SIZE.set(blacklist.read().await.len() as i64);
+
+    let encoder = TextEncoder::new();
+    let mut buffer = Vec::new();
+    encoder.encode(&REGISTRY.gather(), &mut buffer).unwrap();
+    warp::reply::with_header(buffer, "content-type", encoder.format_type())
+}
@@ -0,0 +1,395 @@
+use std::future::Future;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::os::unix::fs::FileTypeExt;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use std::{fs, io, process};
+
+use futures::future;
+use listenfd::ListenFd;
+use tokio::net::{TcpListener, UnixListener};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::{mpsc, watch};
+use tokio_rustls::rustls::{Certificate, PrivateKey, ServerConfig};
+use tokio_rustls::TlsAcceptor;
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream, UnixListenerStream};
+use tokio_stream::StreamExt;
+use tracing::{error, info, warn};
+use warp::http::Uri;
+use warp::path::FullPath;
+use warp::{Filter, Rejection, Reply};
+
+/// Where and how to listen
+pub struct Listen {
+    pub addrs: Vec<ListenAddr>,
+    pub tls: Option<TlsFiles>,
+    /// Listen on this port with plain HTTP and redirect every request to HTTPS
+    pub http_redirect_port: Option<u16>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ListenAddr {
+    Tcp(SocketAddr),
+    /// Always plain HTTP, for a reverse proxy on the same host
+    Unix(PathBuf),
+}
+
+impl FromStr for ListenAddr {
+    type Err = String;
+
+    /// `127.0.0.1:3000`, `[::]:3000` or `unix:/path/to/socket`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix("unix:") {
+            Some(path) => Ok(ListenAddr::Unix(PathBuf::from(path))),
+            None => s
+                .parse()
+                .map(ListenAddr::Tcp)
+                .map_err(|e| format!("invalid listen address {s}: {e}")),
+        }
+    }
+}
+
+/// A socket ready to accept connections
+enum Listener {
+    /// Bound by warp itself, so remote addresses are available to filters
+    Addr(SocketAddr),
+    Tcp(TcpListener),
+    Unix(UnixListener, Option<PathBuf>),
+}
+
+impl Listener {
+    fn bind(addr: &ListenAddr) -> io::Result<Self> {
+        match addr {
+            ListenAddr::Tcp(a) => Ok(Listener::Addr(*a)),
+            ListenAddr::Unix(path) => {
+                // socket file left by last run, never remove anything else
+                match fs::symlink_metadata(path) {
+                    Ok(m) if m.file_type().is_socket() => fs::remove_file(path)?,
+                    Ok(_) => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::AlreadyExists,
+                            format!("{} exists and is not a socket", path.display()),
+                        ))
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                    Err(e) => return Err(e),
+                }
+                Ok(Listener::Unix(
+                    UnixListener::bind(path)?,
+                    Some(path.clone()),
+                ))
+            }
+        }
+    }
+
+    /// Sockets passed by systemd socket activation
+    fn from_systemd() -> io::Result<Vec<Self>> {
+        let mut fds = ListenFd::from_env();
+        let mut listeners = Vec::new();
+        for i in 0..fds.len() {
+            if let Ok(Some(l)) = fds.take_tcp_listener(i) {
+                l.set_nonblocking(true)?;
+                listeners.push(Listener::Tcp(TcpListener::from_std(l)?));
+            } else if let Some(l) = fds.take_unix_listener(i)? {
+                l.set_nonblocking(true)?;
+                listeners.push(Listener::Unix(UnixListener::from_std(l)?, None));
+            }
+        }
+        Ok(listeners)
+    }
+}
+
+pub struct TlsFiles {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+impl TlsFiles {
+    fn load(&self) -> Result<Arc<ServerConfig>, String> {
+        let cert = fs::read(&self.cert).map_err(|e| format!("fail to read certificate: {e}"))?;
+        let certs: Vec<Certificate> = rustls_pemfile::certs(&mut cert.as_slice())
+            .map_err(|e| format!("fail to parse certificate: {e}"))?
+            .into_iter()
+            .map(Certificate)
+            .collect();
+        if certs.is_empty() {
+            return Err("no certificate found".to_string());
+        }
+
+        let key = fs::read(&self.key).map_err(|e| format!("fail to read private key: {e}"))?;
+        let key = rustls_pemfile::pkcs8_private_keys(&mut key.as_slice())
+            .ok()
+            .and_then(|mut k| k.pop())
+            .or_else(|| {
+                rustls_pemfile::rsa_private_keys(&mut key.as_slice())
+                    .ok()
+                    .and_then(|mut k| k.pop())
+            })
+            .ok_or_else(|| "no private key found".to_string())?;
+
+        let mut config = ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(certs, PrivateKey(key))
+            .map_err(|e| format!("invalid certificate or private key: {e}"))?;
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        Ok(Arc::new(config))
+    }
+}
+
+/// Serve routes on every listen address or systemd socket until the stop channel is notified,
+/// and wait for in-flight requests
+pub async fn serve<F, R>(routes: F, listen: Listen, stop: watch::Receiver<()>)
+where
+    F: Filter<Extract = (R,), Error = Rejection> + Clone + Send + Sync + 'static,
+    R: Reply + 'static,
+{
+    let listeners = Listener::from_systemd()
+        .and_then(|l| {
+            if l.is_empty() {
+                listen.addrs.iter().map(Listener::bind).collect()
+            } else {
+                info!("use {} sockets from systemd", l.len());
+                Ok(l)
+            }
+        })
+        .unwrap_or_else(|e| {
+            error!("fail to listen: {e}");
+            process::exit(1);
+        });
+
+    let acceptor = listen
+        .tls
+        .map(|files| reloadable_acceptor(files, stop.clone()));
+
+    // bind everything before spawning any server, so a failing address stops the startup
+    let mut servers: Vec<Pin<Box<dyn Future<Output = ()> + Send>>> = Vec::new();
+    if let (Some(_), Some(port)) = (&acceptor, listen.http_redirect_port) {
+        let ip = listen
+            .addrs
+            .iter()
+            .find_map(|a| match a {
+                ListenAddr::Tcp(addr) => Some(addr.ip()),
+                ListenAddr::Unix(_) => None,
+            })
+            .unwrap_or(Ipv4Addr::UNSPECIFIED.into());
+        servers.push(Box::pin(serve_redirect(
+            SocketAddr::new(ip, port),
+            https_port(&listen.addrs),
+            stop.clone(),
+        )));
+    }
+
+    for listener in listeners {
+        let routes = routes.clone();
+        let stop = stop.clone();
+        let server: Pin<Box<dyn Future<Output = ()> + Send>> = match (listener, &acceptor) {
+            (Listener::Addr(addr), None) => {
+                let (addr, server) = warp::serve(routes)
+                    .try_bind_with_graceful_shutdown(addr, stopped(stop))
+                    .unwrap_or_else(|e| {
+                        error!("fail to listen on {addr}: {e}");
+                        process::exit(1);
+                    });
+                info!("listening on http://{addr}");
+                Box::pin(server)
+            }
+            (Listener::Addr(addr), Some(acceptor)) => {
+                let listener = TcpListener::bind(addr).await.unwrap_or_else(|e| {
+                    error!("fail to listen on {addr}: {e}");
+                    process::exit(1);
+                });
+                info!("listening on https://{addr}");
+                Box::pin(serve_tls(routes, listener, acceptor.clone(), stop))
+            }
+            (Listener::Tcp(listener), None) => {
+                info!("listening on http://{}", listener.local_addr().unwrap());
+                let incoming = TcpListenerStream::new(listener);
+                Box::pin(
+                    warp::serve(routes)
+                        .serve_incoming_with_graceful_shutdown(incoming, stopped(stop)),
+                )
+            }
+            (Listener::Tcp(listener), Some(acceptor)) => {
+                info!("listening on https://{}", listener.local_addr().unwrap());
+                Box::pin(serve_tls(routes, listener, acceptor.clone(), stop))
+            }
+            (Listener::Unix(listener, path), _) => {
+                let addr = listener.local_addr().unwrap();
+                let name = addr
+                    .as_pathname()
+                    .map_or("unnamed".into(), |p| p.display().to_string());
+                info!("listening on unix:{name}");
+                let incoming = UnixListenerStream::new(listener);
+                Box::pin(async move {
+                    warp::serve(routes)
+                        .serve_incoming_with_graceful_shutdown(incoming, stopped(stop))
+                        .await;
+                    if let Some(p) = path {
+                        fs::remove_file(p).ok();
+                    }
+                })
+            }
+        };
+        servers.push(server);
+    }
+
+    future::join_all(servers.into_iter().map(tokio::spawn)).await;
+}
+
+/// Port used in HTTP to HTTPS redirect
+fn https_port(addrs: &[ListenAddr]) -> u16 {
+    addrs
+        .iter()
+        .find_map(|a| match a {
+            ListenAddr::Tcp(addr) => Some(addr.port()),
+            ListenAddr::Unix(_) => None,
+        })
+        .unwrap_or(443)
+}
+
+async fn stopped(mut stop: watch::Receiver<()>) {
+    stop.changed().await.ok();
+}
+
+/// Load the TLS certificate, and reload it on SIGHUP until stopped
+fn reloadable_acceptor(files: TlsFiles, mut stop: watch::Receiver<()>) -> Arc<RwLock<TlsAcceptor>> {
+    let config = files.load().unwrap_or_else(|e| {
+        error!("fail to load TLS certificate: {e}");
+        process::exit(1);
+    });
+    let acceptor = Arc::new(RwLock::new(TlsAcceptor::from(config)));
+
+    let reloaded = Arc::clone(&acceptor);
+    tokio::spawn(async move {
+        let mut hangup = signal(SignalKind::hangup()).unwrap();
+        loop {
+            tokio::select! {
+                _ = stop.changed() => break,
+                _ = hangup.recv() => match files.load() {
+                    Ok(config) => {
+                        info!("TLS certificate reloaded");
+                        *reloaded.write().unwrap() = TlsAcceptor::from(config);
+                    }
+                    Err(e) => error!("fail to reload TLS certificate, keep the current one: {e}"),
+                },
+            }
+        }
+    });
+    acceptor
+}
+
+/// Clients not finishing the handshake in time are dropped, so they can't hold sockets forever
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Accept TLS connections with the current certificate.
+/// Handshakes run in their own tasks so a slow client can't block accepting
+async fn serve_tls<F, R>(
+    routes: F,
+    listener: TcpListener,
+    acceptor: Arc<RwLock<TlsAcceptor>>,
+    stop: watch::Receiver<()>,
+) where
+    F: Filter<Extract = (R,), Error = Rejection> + Clone + Send + Sync + 'static,
+    R: Reply + 'static,
+{
+    let (tx, rx) = mpsc::channel(64);
+    let mut accept_stop = stop.clone();
+    let accept = tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                _ = accept_stop.changed() => break,
+                conn = listener.accept() => {
+                    let (stream, remote) = match conn {
+                        Ok(c) => c,
+                        Err(e) => {
+                            warn!("fail to accept connection: {e}");
+                            continue;
+                        }
+                    };
+                    let acceptor = acceptor.read().unwrap().clone();
+                    let tx = tx.clone();
+                    tokio::spawn(async move {
+                        match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                            Ok(Ok(s)) => {
+                                tx.send(s).await.ok();
+                            }
+                            Ok(Err(e)) => info!("TLS handshake with {remote} failed: {e}"),
+                            Err(_) => info!("TLS handshake with {remote} timed out"),
+                        }
+                    });
+                }
+            }
+        }
+    });
+
+    let incoming = ReceiverStream::new(rx).map(Ok::<_, io::Error>);
+    warp::serve(routes)
+        .serve_incoming_with_graceful_shutdown(incoming, stopped(stop))
+        .await;
+    accept.await.ok();
+}
+
+/// Redirect every plain HTTP request to the same host and path on the HTTPS port,
+/// the address is bound before returning
+fn serve_redirect(
+    addr: SocketAddr,
+    https_port: u16,
+    stop: watch::Receiver<()>,
+) -> impl Future<Output = ()> + Send {
+    let query = warp::query::raw()
+        .map(|q: String| format!("?{q}"))
+        .or(warp::any().map(String::new))
+        .unify();
+    let redirect = warp::path::full()
+        .and(query)
+        .and(warp::header::optional::<String>("host"))
+        .and_then(move |path: FullPath, query: String, host: Option<String>| {
+            let host = host.unwrap_or_else(|| addr.ip().to_string());
+            let host = strip_port(&host);
+            let authority = if https_port == 443 {
+                host.to_string()
+            } else {
+                format!("{host}:{https_port}")
+            };
+            let uri = format!("https://{authority}{}{query}", path.as_str());
+            match uri.parse::<Uri>() {
+                Ok(u) => future::ok(warp::redirect::permanent(u)),
+                Err(_) => future::err(warp::reject()),
+            }
+        });
+
+    let (addr, server) = warp::serve(redirect)
+        .try_bind_with_graceful_shutdown(addr, stopped(stop))
+        .unwrap_or_else(|e| {
+            error!("fail to listen on {addr}: {e}");
+            process::exit(1);
+        });
+    info!("redirecting http://{addr} to https");
+    server
+}
+
+/// Host header may contain the HTTP port, e.g. example.com:8080 or [::1]:8080
+fn strip_port(host: &str) -> &str {
+    match host.rsplit_once(':') {
+        Some((h, port))
+            if !port.is_empty()
+                && port.chars().all(|c| c.is_ascii_digit())
+                && (!h.contains(':') || h.ends_with(']')) =>
+        {
+            h
+        }
+        _ => host,
+    }
+}
+
+#[test]
+fn strip_host_port() {
+    assert_eq!(strip_port("example.com:8080"), "example.com");
+    assert_eq!(strip_port("example.com"), "example.com");
+    assert_eq!(strip_port("[::1]:8080"), "[::1]");
+    assert_eq!(strip_port("[::1]"), "[::1]");
+}
@@ -0,0 +1,35 @@
+use std::future::Future;
+use std::time::Duration;
+
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+/// Wait for SIGTERM or SIGINT, return the signal name as the shutdown reason
+pub async fn wait_for_signal() -> &'static str {
+    let mut terminate = signal(SignalKind::terminate()).unwrap();
+    tokio::select! {
+        _ = terminate.recv() => "SIGTERM",
+        _ = tokio::signal::ctrl_c() => "SIGINT",
+    }
+}
+
+/// Run the servers built by `serve` until a shutdown signal, then notify them through the watch channel
+/// to stop accepting connections, and wait at most `drain_timeout` for in-flight requests
+pub async fn run_until_signal<S, F>(serve: S, drain_timeout: Duration)
+where
+    S: FnOnce(watch::Receiver<()>) -> F,
+    F: Future<Output = ()> + Send + 'static,
+{
+    let (tx, rx) = watch::channel(());
+    let server = tokio::spawn(serve(rx));
+
+    let reason = wait_for_signal().await;
+    info!("received {reason}, shutting down");
+    tx.send(()).ok();
+
+    match tokio::time::timeout(drain_timeout, server).await {
+        Ok(_) => info!("in-flight requests are drained"),
+        Err(_) => warn!("in-flight requests are not drained in {drain_timeout:?}, stop waiting"),
+    }
+}
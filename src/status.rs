@@ -0,0 +1,115 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use tokio::sync::RwLock;
+use warp::http::StatusCode;
+use warp::Reply;
+
+use crate::blacklist::Blacklist;
+use crate::cache::RssCache;
+
+static START: LazyLock<Instant> = LazyLock::new(Instant::now);
+
+static SOURCES: LazyLock<Mutex<BTreeMap<String, SourceStatus>>> =
+    LazyLock::new(|| Mutex::new(BTreeMap::new()));
+
+/// Fetch history of an upstream source, times are unix seconds
+#[derive(Serialize, Default, Clone)]
+pub struct SourceStatus {
+    last_success: Option<u64>,
+    last_error: Option<String>,
+    last_error_time: Option<u64>,
+    /// Time of the first error after the last success, None when the last fetch succeeded
+    failing_since: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct BlacklistStatus {
+    /// File path, or "embedded" for the default blacklist
+    source: String,
+    loaded_at: Option<u64>,
+    size: usize,
+}
+
+#[derive(Serialize)]
+struct Status {
+    version: &'static str,
+    uptime_secs: u64,
+    sources: BTreeMap<String, SourceStatus>,
+    /// Cache key to cache age in seconds
+    caches: BTreeMap<String, u64>,
+    blacklist: BlacklistStatus,
+}
+
+pub fn unix_secs(t: SystemTime) -> u64 {
+    t.duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+/// Start counting uptime
+pub fn init() {
+    LazyLock::force(&START);
+}
+
+pub fn record_fetch(source: &str, error: Option<String>) {
+    let now = unix_secs(SystemTime::now());
+    let mut sources = SOURCES.lock().unwrap();
+    let status = sources.entry(source.to_string()).or_default();
+    match error {
+        None => {
+            status.last_success = Some(now);
+            status.failing_since = None;
+        }
+        Some(e) => {
+            status.last_error = Some(e);
+            status.last_error_time = Some(now);
+            status.failing_since.get_or_insert(now);
+        }
+    }
+}
+
+/// GET /status
+pub async fn status(blacklist: Arc<RwLock<Blacklist>>, cache: Arc<RwLock<RssCache>>) -> impl Reply {
+    let sources = SOURCES.lock().unwrap().clone();
+    let b = blacklist.read().await;
+    let status = Status {
+        version: env!("CARGO_PKG_VERSION"),
+        uptime_secs: START.elapsed().as_secs(),
+        sources,
+        caches: cache
+            .read()
+            .await
+            .ages()
+            .map(|(k, age)| (k.to_string(), age.as_secs()))
+            .collect(),
+        blacklist: BlacklistStatus {
+            source: b.source(),
+            loaded_at: b.loaded_at().map(unix_secs),
+            size: b.len(),
+        },
+    };
+    warp::reply::json(&status)
+}
+
+/// GET /status/live, the process is up and serving
+pub fn live() -> impl Reply {
+    "ok"
+}
+
+/// GET /status/ready, not ready when every fetched source has been failing longer than threshold
+pub fn ready(threshold: Duration) -> impl Reply {
+    let now = unix_secs(SystemTime::now());
+    let sources = SOURCES.lock().unwrap();
+    let all_failing = !sources.is_empty()
+        && sources.values().all(|s| {
+            s.failing_since
+                .is_some_and(|t| now.saturating_sub(t) > threshold.as_secs())
+        });
+
+    if all_failing {
+        warp::reply::with_status("every source is failing", StatusCode::SERVICE_UNAVAILABLE)
+    } else {
+        warp::reply::with_status("ok", StatusCode::OK)
+    }
+}
@@ -0,0 +1,54 @@
+use std::path::PathBuf;
+use std::process;
+
+use handlebars::Handlebars;
+use serde::Serialize;
+use tracing::{error, info};
+
+/// User supplied Handlebars templates of item descriptions, one per source.
+/// Sources without a template use the built-in layout
+#[derive(Default)]
+pub struct Templates {
+    registry: Handlebars<'static>,
+}
+
+impl Templates {
+    /// Load template files by source name, exit when a file is not valid
+    pub fn load(files: &[(&str, Option<PathBuf>)]) -> Self {
+        let mut registry = Handlebars::new();
+        for (source, path) in files {
+            if let Some(p) = path {
+                info!("use {source} template at: {}", p.to_str().unwrap());
+                if let Err(e) = registry.register_template_file(source, p) {
+                    error!("fail to load {source} template: {e}");
+                    process::exit(1);
+                }
+            }
+        }
+        Templates { registry }
+    }
+
+    /// None when the source has no template or rendering fails, then the built-in layout should be used
+    pub fn render<T: Serialize>(&self, source: &str, data: &T) -> Option<String> {
+        if !self.registry.has_template(source) {
+            return None;
+        }
+        self.registry
+            .render(source, data)
+            .map_err(|e| error!("fail to render {source} template: {e}"))
+            .ok()
+    }
+}
+
+#[test]
+fn render_template() {
+    let mut templates = Templates::default();
+    assert!(templates.render("ddys", &()).is_none());
+
+    templates
+        .registry
+        .register_template_string("ddys", "{{title}}: {{#each category}}{{this}} {{/each}}")
+        .unwrap();
+    let data = serde_json::json!({"title": "<a>", "category": ["x", "y"]});
+    assert_eq!(templates.render("ddys", &data).unwrap(), "&lt;a&gt;: x y ");
+}